@@ -0,0 +1,270 @@
+//! Pluggable lease storage, see [`LeaseBackend`].
+mod dynamodb;
+#[cfg(feature = "test-util")]
+mod faulty;
+#[cfg(feature = "test-util")]
+mod memory;
+
+pub use dynamodb::{DynamoDbBackend, SortKey, TableConfig, TableLayout};
+#[cfg(feature = "test-util")]
+pub use faulty::{FaultyBackend, Operation};
+#[cfg(feature = "test-util")]
+pub use memory::MemoryBackend;
+
+use crate::{Error, LeaseInfo, Result};
+use async_trait::async_trait;
+use std::{collections::HashMap, fmt, pin::Pin, time::Duration};
+use tokio_stream::Stream;
+use uuid::Uuid;
+
+/// Stream of held leases, see [`LeaseBackend::list_leases`].
+pub type LeaseStream = Pin<Box<dyn Stream<Item = Result<LeaseInfo>> + Send + 'static>>;
+
+/// Storage of leases used by a [`Client`](crate::Client).
+///
+/// Acquire, extend & release logic lives in the client & [`Lease`](crate::Lease), a backend
+/// only needs to provide atomic conditional writes for each operation. The default
+/// implementation is [`DynamoDbBackend`]. An in-memory backend & a fault injecting wrapper
+/// for tests are available with the `test-util` feature.
+///
+/// A backend must implement putting, extending, releasing & getting exclusive leases and
+/// validation. Other operations support optional client features, e.g. shared leases, & by
+/// default return [`Error::Unsupported`].
+///
+/// Operations that fail their condition, e.g. because the lease is held elsewhere,
+/// should return `Ok` with a [`PutOutcome::Held`] or `false` result rather than an error.
+#[async_trait]
+pub trait LeaseBackend: fmt::Debug + Send + Sync + 'static {
+    /// Put a new exclusive lease, if the key is not held or the lease has expired
+    /// by more than [`PutLease::clock_skew_margin`]. Must also not succeed while
+    /// unexpired shared leases are held.
+    ///
+    /// Returns the new fence token, which must be greater than the previous token
+    /// for the key, or [`PutOutcome::Held`] if the lease is held.
+    async fn put_lease(&self, lease: PutLease) -> Result<PutOutcome>;
+
+    /// Extends a held exclusive lease to `new_lease_v` & a new expiry `ttl` from now.
+    ///
+    /// Must succeed if the lease version is `lease_v` or already `new_lease_v`, so
+    /// retrying an ambiguous failure is safe.
+    ///
+    /// Returns `false` if the lease is no longer held.
+    async fn extend_lease(
+        &self,
+        key: &str,
+        lease_v: Uuid,
+        new_lease_v: Uuid,
+        ttl: Duration,
+    ) -> Result<bool>;
+
+    /// Releases a held exclusive lease, if the lease version is `lease_v`.
+    ///
+    /// Returns `false` if the lease is no longer held.
+    async fn release_lease(&self, key: &str, lease_v: Uuid) -> Result<bool>;
+
+    /// Put a new shared lease holder with id [`PutLease::lease_v`], if the key is not
+    /// held exclusively or the exclusive lease has expired.
+    ///
+    /// Returns the current fence token, or [`PutOutcome::Held`] if the lease is held exclusively.
+    async fn put_shared_lease(&self, lease: PutLease) -> Result<PutOutcome> {
+        let _ = lease;
+        Err(Error::Unsupported("shared leases"))
+    }
+
+    /// Extends a held shared lease to a new expiry `ttl` from now.
+    ///
+    /// Returns `false` if the lease is no longer held by `reader_id`.
+    async fn extend_shared_lease(&self, key: &str, reader_id: Uuid, ttl: Duration) -> Result<bool> {
+        let _ = (key, reader_id, ttl);
+        Err(Error::Unsupported("shared leases"))
+    }
+
+    /// Releases a held shared lease.
+    ///
+    /// Returns `false` if the lease is no longer held by `reader_id`.
+    async fn release_shared_lease(&self, key: &str, reader_id: Uuid) -> Result<bool> {
+        let _ = (key, reader_id);
+        Err(Error::Unsupported("shared leases"))
+    }
+
+    /// Put new exclusive leases for every key together, atomically, each as
+    /// [`LeaseBackend::put_lease`]. All leases have the same [`PutLease::lease_v`].
+    ///
+    /// Returns the new fence tokens, in the order of `leases`, or `None`, putting no
+    /// leases, if any lease is held.
+    async fn put_leases(&self, leases: Vec<PutLease>) -> Result<Option<Vec<u64>>> {
+        let _ = leases;
+        Err(Error::Unsupported("multi-key leases"))
+    }
+
+    /// Extends held exclusive leases for every key together, atomically, each as
+    /// [`LeaseBackend::extend_lease`].
+    ///
+    /// Returns `false`, extending no leases, if any lease is no longer held.
+    async fn extend_leases(
+        &self,
+        keys: &[String],
+        lease_v: Uuid,
+        new_lease_v: Uuid,
+        ttl: Duration,
+    ) -> Result<bool> {
+        let _ = (keys, lease_v, new_lease_v, ttl);
+        Err(Error::Unsupported("multi-key leases"))
+    }
+
+    /// Releases held exclusive leases for every key together, atomically, if the lease
+    /// version of each is `lease_v`.
+    ///
+    /// Returns `false`, releasing no leases, if any lease is no longer held.
+    async fn release_leases(&self, keys: &[String], lease_v: Uuid) -> Result<bool> {
+        let _ = (keys, lease_v);
+        Err(Error::Unsupported("multi-key leases"))
+    }
+
+    /// Put a new exclusive hierarchical lease, as [`LeaseBackend::put_lease`], if also no
+    /// ancestor key is held exclusively. Adds an intent marker with id [`Intents::id`],
+    /// expiring `ttl` from now, to each ancestor atomically with the lease.
+    ///
+    /// Exclusive leases must not be put while a key has unexpired intent markers, i.e.
+    /// while a descendant key is held.
+    ///
+    /// See [`ClientBuilder::hierarchical`](crate::ClientBuilder::hierarchical).
+    async fn put_hierarchical_lease(
+        &self,
+        lease: PutLease,
+        intents: &Intents,
+    ) -> Result<PutOutcome> {
+        let _ = (lease, intents);
+        Err(Error::Unsupported("hierarchical leases"))
+    }
+
+    /// Extends a held exclusive hierarchical lease, as [`LeaseBackend::extend_lease`],
+    /// & its ancestor intent markers atomically.
+    ///
+    /// Returns `false` if the lease, or any intent marker, is no longer held.
+    async fn extend_hierarchical_lease(
+        &self,
+        key: &str,
+        intents: &Intents,
+        lease_v: Uuid,
+        new_lease_v: Uuid,
+        ttl: Duration,
+    ) -> Result<bool> {
+        let _ = (key, intents, lease_v, new_lease_v, ttl);
+        Err(Error::Unsupported("hierarchical leases"))
+    }
+
+    /// Releases a held exclusive hierarchical lease, as [`LeaseBackend::release_lease`],
+    /// & removes its ancestor intent markers. Intent markers should be removed even if the
+    /// lease is no longer held.
+    ///
+    /// Returns `false` if the lease, or any intent marker, was no longer held.
+    async fn release_hierarchical_lease(
+        &self,
+        key: &str,
+        intents: &Intents,
+        lease_v: Uuid,
+    ) -> Result<bool> {
+        let _ = (key, intents, lease_v);
+        Err(Error::Unsupported("hierarchical leases"))
+    }
+
+    /// Adds `ticket` to the back of the key's queue of fair waiters, expiring `ttl` from now.
+    /// The queue must not be removed, e.g. by a ttl, before its tickets expire.
+    ///
+    /// See [`ClientBuilder::fair`](crate::ClientBuilder::fair).
+    async fn enqueue(&self, key: &str, ticket: Uuid, ttl: Duration) -> Result<()> {
+        let _ = (key, ticket, ttl);
+        Err(Error::Unsupported("fair mode"))
+    }
+
+    /// Put a new exclusive lease, as [`LeaseBackend::put_lease`], only if `ticket` is at
+    /// the front of the key's queue. Expired tickets, including the clock skew margin,
+    /// are removed from the queue & the ticket is removed on success.
+    ///
+    /// If not acquired the ticket's expiry is extended to `ttl` from now. If the ticket
+    /// has already expired & been removed it is added to the back of the queue.
+    async fn put_queued_lease(&self, lease: PutLease, ticket: Uuid) -> Result<PutOutcome> {
+        let _ = (lease, ticket);
+        Err(Error::Unsupported("fair mode"))
+    }
+
+    /// Removes `ticket` from the key's queue.
+    async fn dequeue(&self, key: &str, ticket: Uuid) -> Result<()> {
+        let _ = (key, ticket);
+        Err(Error::Unsupported("fair mode"))
+    }
+
+    /// Waits until the lease for `key` may have changed, e.g. been released, or `max_wait`
+    /// elapses. Used between attempts to acquire a lease.
+    ///
+    /// Default waits for `max_wait`, i.e. polling according to [`ClientBuilder::acquire_backoff`](crate::ClientBuilder::acquire_backoff).
+    async fn wait_for_change(&self, key: &str, max_wait: Duration) {
+        let _ = key;
+        tokio::time::sleep(max_wait).await;
+    }
+
+    /// Returns info about the current exclusive holder of the lease for `key`.
+    async fn get_lease(&self, key: &str) -> Result<Option<LeaseInfo>>;
+
+    /// Returns a stream of all held exclusive leases, optionally only those with keys
+    /// starting with `key_prefix`.
+    fn list_leases(&self, key_prefix: Option<String>) -> LeaseStream {
+        let _ = key_prefix;
+        Box::pin(tokio_stream::once(Err(Error::Unsupported(
+            "listing leases",
+        ))))
+    }
+
+    /// Checks the backend is available & correctly configured.
+    async fn validate(&self) -> Result<()>;
+}
+
+/// A request to put a new lease, see [`LeaseBackend::put_lease`].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct PutLease {
+    /// The lease key.
+    pub key: String,
+    /// The new lease version, or shared holder id.
+    pub lease_v: Uuid,
+    /// The lease expires this long from now, unless extended.
+    pub ttl: Duration,
+    /// Margin after the expiry of an existing lease before it may be acquired.
+    pub clock_skew_margin: Duration,
+    /// The owner id of the new holder.
+    pub owner_id: String,
+    /// Lease metadata, see [`Client::with_metadata`](crate::Client::with_metadata).
+    pub metadata: HashMap<String, String>,
+}
+
+/// Intent markers of a hierarchical lease on the ancestors of its key,
+/// see [`LeaseBackend::put_hierarchical_lease`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Intents {
+    /// Ancestor keys, e.g. `["a", "a/b"]` for key `"a/b/c"`.
+    pub ancestors: Vec<String>,
+    /// Id of the intent marker on each ancestor, fixed while the lease is held.
+    pub id: Uuid,
+}
+
+/// Outcome of putting a new lease, see [`LeaseBackend::put_lease`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PutOutcome {
+    /// The lease was acquired.
+    Acquired {
+        /// The key's fence token.
+        fence: u64,
+    },
+    /// The lease is held elsewhere.
+    Held {
+        /// Time until the current lease expires, unless extended, if known.
+        /// Zero if already expired.
+        ///
+        /// Used to avoid waiting longer than necessary to try again, see
+        /// [`AcquireBackoff`](crate::AcquireBackoff).
+        expires_in: Option<Duration>,
+    },
+}
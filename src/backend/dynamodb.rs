@@ -0,0 +1,1631 @@
+mod layout;
+#[cfg(feature = "streams")]
+mod streams;
+mod table;
+
+pub use layout::{SortKey, TableLayout};
+pub use table::TableConfig;
+
+use crate::{
+    backend::{Intents, LeaseBackend, LeaseStream, PutLease, PutOutcome},
+    Error, LeaseInfo, Result,
+};
+use async_trait::async_trait;
+use aws_sdk_dynamodb::{
+    error::SdkError,
+    operation::{
+        describe_table::DescribeTableError, transact_write_items::TransactWriteItemsError,
+        update_item::UpdateItemError,
+    },
+    types::{
+        AttributeDefinition, AttributeValue, CancellationReason, Get, KeySchemaElement, KeyType,
+        ReturnValue, ReturnValuesOnConditionCheckFailure, ScalarAttributeType, TransactGetItem,
+        TransactWriteItem, Update,
+    },
+};
+use std::{collections::HashMap, future::Future, sync::Arc, time::Duration};
+use time::OffsetDateTime;
+use tokio_stream::StreamExt;
+use tracing::instrument;
+use uuid::Uuid;
+
+const FENCE_FIELD: &str = "fence";
+const OWNER_FIELD: &str = "owner";
+const METADATA_FIELD: &str = "metadata";
+const READERS_FIELD: &str = "readers";
+const QUEUE_FIELD: &str = "queue";
+const TICKET_FIELD: &str = "ticket";
+const TICKET_EXPIRY_FIELD: &str = "expiry";
+const INTENTS_FIELD: &str = "intents";
+/// Expiry of a held exclusive lease, set when the `lease_expiry` ttl is raised past it
+/// to keep queued tickets, see [`DynamoDbBackend::keep_queued_item`].
+const HELD_EXPIRY_FIELD: &str = "held_expiry";
+
+type Item = HashMap<String, AttributeValue>;
+
+/// Condition that the item is not held exclusively, or the lease has expired before `:expired`.
+const EXPIRED_CONDITION: &str = "(attribute_not_exists(#lease_v) OR #expiry < :expired \
+    OR held_expiry < :expired)";
+
+/// Update & condition expressions with their attribute names & values.
+#[derive(Debug, Clone)]
+struct Expression {
+    update: String,
+    condition: String,
+    names: HashMap<String, String>,
+    values: Item,
+}
+
+/// The default [`LeaseBackend`], storing leases in a dynamodb table.
+///
+/// See [`ClientBuilder::build_and_check_db`](crate::ClientBuilder::build_and_check_db).
+#[derive(Debug, Clone)]
+pub struct DynamoDbBackend {
+    client: aws_sdk_dynamodb::Client,
+    table_name: String,
+    layout: Arc<TableLayout>,
+    #[cfg(feature = "streams")]
+    streams: Option<streams::StreamWatcher>,
+}
+
+impl DynamoDbBackend {
+    /// Returns a new backend using the given lease table.
+    ///
+    /// The table schema is checked by [`LeaseBackend::validate`].
+    pub fn new(client: aws_sdk_dynamodb::Client, table_name: impl Into<String>) -> Self {
+        Self {
+            client,
+            table_name: table_name.into(),
+            layout: <_>::default(),
+            #[cfg(feature = "streams")]
+            streams: None,
+        }
+    }
+
+    /// Use the given table key schema & attribute names, e.g. to store leases in an
+    /// existing table with other items.
+    ///
+    /// Default [`TableLayout::default`].
+    pub fn with_layout(mut self, layout: TableLayout) -> Self {
+        self.layout = Arc::new(layout);
+        self
+    }
+
+    /// Use the lease table's dynamodb stream to wait for leases to change, instead of
+    /// waiting for the full acquire cooldown between attempts.
+    ///
+    /// If the table does not have a stream enabled waiting falls back to the cooldown.
+    /// With a `NEW_AND_OLD_IMAGES` stream, see [`TableConfig::stream`], waiters are only woken
+    /// when a lease may have been released. Other stream view types wake waiters on every
+    /// change to the lease, e.g. extensions.
+    #[cfg(feature = "streams")]
+    pub fn with_streams(mut self, streams_client: aws_sdk_dynamodbstreams::Client) -> Self {
+        self.streams = Some(streams::StreamWatcher::new(
+            streams_client,
+            self.client.clone(),
+            self.table_name.clone(),
+        ));
+        self
+    }
+
+    /// Returns the lease table name.
+    pub fn table_name(&self) -> &str {
+        &self.table_name
+    }
+
+    /// Returns the lease table layout.
+    pub fn layout(&self) -> &TableLayout {
+        &self.layout
+    }
+
+    /// Returns the primary key of the item for the lease `key`.
+    fn item_key(&self, key: &str) -> Item {
+        let mut item_key = Item::new();
+        item_key.insert(self.layout.key.clone(), AttributeValue::S(key.into()));
+        if let Some((name, value)) = &self.layout.sort_key {
+            item_key.insert(name.clone(), AttributeValue::S(value.value(key)));
+        }
+        item_key
+    }
+
+    /// Put a new exclusive lease into the db, removing any expired shared holders that
+    /// would prevent this. If a `ticket` is given it must be at the front of the queue.
+    async fn put_exclusive_lease(
+        &self,
+        lease: &PutLease,
+        ticket: Option<&str>,
+    ) -> Result<PutOutcome> {
+        let item = match self.put_lease_item(lease, ticket).await? {
+            Ok(fence) => return Ok(PutOutcome::Acquired { fence }),
+            Err(item) => item,
+        };
+        // expired shared holders may be blocking, if so remove them & try again
+        if let Some(item) = &item {
+            if self
+                .remove_expired_holders(&lease.key, item, lease.clock_skew_margin)
+                .await?
+            {
+                return Ok(match self.put_lease_item(lease, ticket).await? {
+                    Ok(fence) => PutOutcome::Acquired { fence },
+                    Err(item) => self.held(item.as_ref()),
+                });
+            }
+        }
+        Ok(self.held(item.as_ref()))
+    }
+
+    /// Put a new exclusive lease into the db. If a `ticket` is given it must be at the
+    /// front of the queue & is removed.
+    ///
+    /// Returns `Ok(Err(_))` if the lease is held, with the existing item if returned by the db.
+    async fn put_lease_item(
+        &self,
+        lease: &PutLease,
+        ticket: Option<&str>,
+    ) -> Result<Result<u64, Option<Item>>> {
+        let expr = self.put_lease_expression(lease, ticket);
+        let put = self
+            .client
+            .update_item()
+            .table_name(&self.table_name)
+            .set_key(Some(self.item_key(&lease.key)))
+            .update_expression(expr.update)
+            .condition_expression(expr.condition)
+            .set_expression_attribute_names(Some(expr.names))
+            .set_expression_attribute_values(Some(expr.values))
+            .return_values(ReturnValue::UpdatedNew)
+            .return_values_on_condition_check_failure(ReturnValuesOnConditionCheckFailure::AllOld);
+
+        match put.send().await {
+            Err(err) => match condition_failed_item(&err) {
+                Some(item) => Ok(Err(item)),
+                None => Err(err.into()),
+            },
+            Ok(out) => {
+                let fence = out
+                    .attributes()
+                    .and_then(|attrs| attrs.get(FENCE_FIELD))
+                    .and_then(|fence| fence.as_n().ok())
+                    .and_then(|fence| fence.parse().ok())
+                    .ok_or_else(|| {
+                        Error::UnexpectedResponse(format!("missing or invalid {FENCE_FIELD}"))
+                    })?;
+                Ok(Ok(fence))
+            }
+        }
+    }
+
+    /// Raises the `lease_expiry` ttl of `item` to `expiry_timestamp`, if earlier, so the item
+    /// is not removed while it has queued tickets that expire later.
+    ///
+    /// If the lease is held its own expiry is kept in `held_expiry`, so raising the ttl
+    /// does not also extend the lease.
+    async fn keep_queued_item(&self, key: &str, item: &Item, expiry_timestamp: i64) -> Result<()> {
+        let expires_before = item
+            .get(&self.layout.lease_expiry)
+            .and_then(|expiry| expiry.as_n().ok())
+            .and_then(|expiry| expiry.parse::<i64>().ok())
+            .is_some_and(|expiry| expiry < expiry_timestamp);
+        if !expires_before {
+            return Ok(());
+        }
+
+        let extend = self
+            .client
+            .update_item()
+            .table_name(&self.table_name)
+            .set_key(Some(self.item_key(key)))
+            .expression_attribute_names("#lease_v", &self.layout.lease_version)
+            .expression_attribute_names("#expiry", &self.layout.lease_expiry)
+            .expression_attribute_values(
+                ":expiry",
+                AttributeValue::N(expiry_timestamp.to_string()),
+            );
+        let extend = match item.get(&self.layout.lease_version) {
+            // only if still the same lease, so `held_expiry` is correct
+            Some(lease_v) => extend
+                .update_expression(format!(
+                    "SET #expiry = :expiry, \
+                    {HELD_EXPIRY_FIELD} = if_not_exists({HELD_EXPIRY_FIELD}, #expiry)"
+                ))
+                .condition_expression("#lease_v = :lease_v AND #expiry < :expiry")
+                .expression_attribute_values(":lease_v", lease_v.clone()),
+            None => extend
+                .update_expression("SET #expiry = :expiry")
+                .condition_expression("attribute_not_exists(#lease_v) AND #expiry < :expiry"),
+        };
+        match extend.send().await {
+            Err(err) => match condition_failed_item(&err) {
+                // acquired, extended or raised in the meantime, retried on the next attempt
+                Some(_) => Ok(()),
+                None => Err(err.into()),
+            },
+            Ok(_) => Ok(()),
+        }
+    }
+
+    /// Returns the update & condition expressions to put a new exclusive lease,
+    /// see [`DynamoDbBackend::put_lease_item`].
+    fn put_lease_expression(&self, lease: &PutLease, ticket: Option<&str>) -> Expression {
+        let now = OffsetDateTime::now_utc();
+        let expiry_timestamp = now.unix_timestamp() + seconds(lease.ttl);
+        let expired_timestamp = now.unix_timestamp() - seconds(lease.clock_skew_margin);
+        // Fence tokens start from the current unix timestamp in millis. So if the item
+        // is removed by ttl new fence tokens will still be higher than any previous ones.
+        let fence_seed = now.unix_timestamp_nanos() / 1_000_000;
+
+        let mut update = format!(
+            "SET #lease_v=:lease_v, #expiry=:expiry, \
+            {FENCE_FIELD}=if_not_exists({FENCE_FIELD}, :fence_seed) + :one, #owner=:owner"
+        );
+        let mut removes = vec![HELD_EXPIRY_FIELD.to_owned()];
+        let mut values: Item = [
+            (":lease_v", AttributeValue::S(lease.lease_v.to_string())),
+            (":expiry", AttributeValue::N(expiry_timestamp.to_string())),
+            (":expired", AttributeValue::N(expired_timestamp.to_string())),
+            (":fence_seed", AttributeValue::N(fence_seed.to_string())),
+            (":one", AttributeValue::N("1".into())),
+            (":zero", AttributeValue::N("0".into())),
+            (":owner", AttributeValue::S(lease.owner_id.clone())),
+        ]
+        .map(|(name, value)| (name.to_owned(), value))
+        .into();
+        match lease.metadata.is_empty() {
+            true => removes.push(METADATA_FIELD.to_owned()),
+            false => {
+                update += &format!(", {METADATA_FIELD}=:metadata");
+                let metadata = lease
+                    .metadata
+                    .iter()
+                    .map(|(k, v)| (k.clone(), AttributeValue::S(v.clone())))
+                    .collect();
+                values.insert(":metadata".into(), AttributeValue::M(metadata));
+            }
+        }
+        let mut condition = format!(
+            "{EXPIRED_CONDITION} \
+            AND (attribute_not_exists({READERS_FIELD}) OR size({READERS_FIELD}) = :zero) \
+            AND (attribute_not_exists({INTENTS_FIELD}) OR size({INTENTS_FIELD}) = :zero)"
+        );
+        if let Some(ticket) = ticket {
+            removes.push(format!("{QUEUE_FIELD}[0]"));
+            condition += &format!(" AND {QUEUE_FIELD}[0].{TICKET_FIELD} = :ticket");
+            values.insert(":ticket".into(), AttributeValue::S(ticket.into()));
+        }
+        if !removes.is_empty() {
+            update += &format!(" REMOVE {}", removes.join(", "));
+        }
+
+        Expression {
+            update,
+            condition,
+            names: [
+                // "owner" is a reserved word
+                ("#owner", OWNER_FIELD),
+                ("#lease_v", &self.layout.lease_version),
+                ("#expiry", &self.layout.lease_expiry),
+            ]
+            .map(|(alias, name)| (alias.to_owned(), name.to_owned()))
+            .into(),
+            values,
+        }
+    }
+
+    /// Puts new exclusive leases in a single transaction.
+    ///
+    /// Returns `Ok(Err(_))` if any lease is held, with the existing items, in order, if
+    /// returned by the db.
+    async fn transact_put_leases(
+        &self,
+        leases: &[PutLease],
+    ) -> Result<Result<(), Vec<Option<Item>>>> {
+        let items = leases
+            .iter()
+            .map(|lease| self.transact_update(&lease.key, self.put_lease_expression(lease, None)));
+
+        match self
+            .client
+            .transact_write_items()
+            .set_transact_items(Some(items.collect()))
+            .send()
+            .await
+        {
+            Ok(_) => Ok(Ok(())),
+            Err(err) => match cancellation_reasons(&err) {
+                // another transaction concurrently writing the items is also contention
+                Some(reasons)
+                    if reasons.iter().any(|r| {
+                        matches!(
+                            r.code(),
+                            Some("ConditionalCheckFailed" | "TransactionConflict")
+                        )
+                    }) =>
+                {
+                    let mut items: Vec<_> = reasons.iter().map(|r| r.item().cloned()).collect();
+                    items.resize(leases.len(), None);
+                    Ok(Err(items))
+                }
+                _ => Err(err.into()),
+            },
+        }
+    }
+
+    /// Returns the fence tokens of the lease items for `keys`, in order, in a single
+    /// consistent read.
+    async fn get_fences(&self, keys: impl Iterator<Item = &str>) -> Result<Vec<u64>> {
+        let gets = keys.map(|key| {
+            let get = Get::builder()
+                .table_name(&self.table_name)
+                .set_key(Some(self.item_key(key)))
+                .projection_expression(FENCE_FIELD)
+                .build();
+            TransactGetItem::builder().get(get).build()
+        });
+        let out = self
+            .client
+            .transact_get_items()
+            .set_transact_items(Some(gets.collect()))
+            .send()
+            .await?;
+
+        out.responses()
+            .unwrap_or_default()
+            .iter()
+            .map(|response| {
+                response
+                    .item()
+                    .and_then(|item| item.get(FENCE_FIELD))
+                    .and_then(|fence| fence.as_n().ok())
+                    .and_then(|fence| fence.parse().ok())
+                    .ok_or_else(|| {
+                        Error::UnexpectedResponse(format!("missing or invalid {FENCE_FIELD}"))
+                    })
+            })
+            .collect()
+    }
+
+    /// Returns the fence tokens of newly put lease items for `keys`, in order, retrying
+    /// the read up to 3 attempts.
+    ///
+    /// If the fences cannot be read the leases are released by `release` before returning
+    /// the error, so they are not left held by no one until expiry.
+    async fn put_fences(
+        &self,
+        keys: &[String],
+        release: impl Future<Output = Result<bool>>,
+    ) -> Result<Vec<u64>> {
+        let mut attempt = 1;
+        let err = loop {
+            match self.get_fences(keys.iter().map(String::as_str)).await {
+                Ok(fences) => return Ok(fences),
+                Err(err) if attempt >= 3 => break err,
+                Err(err) => {
+                    tracing::warn!(attempt, "Failed to read fence tokens, retrying: {err}");
+                    tokio::time::sleep(Duration::from_millis(100 * attempt)).await;
+                    attempt += 1;
+                }
+            }
+        };
+        if let Err(release_err) = release.await {
+            tracing::warn!(
+                "Failed to release leases after failing to read fence tokens: {release_err}"
+            );
+        }
+        Err(err)
+    }
+
+    /// Returns a transaction item applying `expr` to the lease item for `key`.
+    fn transact_update(&self, key: &str, expr: Expression) -> TransactWriteItem {
+        let update = Update::builder()
+            .table_name(&self.table_name)
+            .set_key(Some(self.item_key(key)))
+            .update_expression(expr.update)
+            .condition_expression(expr.condition)
+            .set_expression_attribute_names(Some(expr.names))
+            // must not be empty if set
+            .set_expression_attribute_values((!expr.values.is_empty()).then_some(expr.values))
+            .return_values_on_condition_check_failure(ReturnValuesOnConditionCheckFailure::AllOld)
+            .build();
+        TransactWriteItem::builder().update(update).build()
+    }
+
+    /// Applies `expr` to the lease item for `key`.
+    ///
+    /// Returns `false` if the condition failed.
+    async fn conditional(&self, key: &str, expr: Expression) -> Result<bool> {
+        let update = self
+            .client
+            .update_item()
+            .table_name(&self.table_name)
+            .set_key(Some(self.item_key(key)))
+            .update_expression(expr.update)
+            .condition_expression(expr.condition)
+            .set_expression_attribute_names(Some(expr.names))
+            // must not be empty if set
+            .set_expression_attribute_values((!expr.values.is_empty()).then_some(expr.values))
+            .send()
+            .await;
+
+        match update {
+            Err(err) => match condition_failed_item(&err) {
+                Some(_) => Ok(false),
+                None => Err(err.into()),
+            },
+            Ok(_) => Ok(true),
+        }
+    }
+
+    /// Applies conditional updates in a single transaction.
+    ///
+    /// Returns `false` if the condition failed for any item.
+    async fn transact_conditional(&self, items: Vec<TransactWriteItem>) -> Result<bool> {
+        match self
+            .client
+            .transact_write_items()
+            .set_transact_items(Some(items))
+            .send()
+            .await
+        {
+            Ok(_) => Ok(true),
+            Err(err) => match cancellation_reasons(&err) {
+                Some(reasons)
+                    if reasons
+                        .iter()
+                        .any(|r| r.code() == Some("ConditionalCheckFailed")) =>
+                {
+                    Ok(false)
+                }
+                // e.g. a transaction conflict, which can be retried
+                _ => Err(err.into()),
+            },
+        }
+    }
+
+    /// Returns the expressions to extend a held exclusive lease to `new_lease_v`,
+    /// see [`LeaseBackend::extend_lease`].
+    fn extend_expression(&self, lease_v: Uuid, new_lease_v: Uuid, ttl: Duration) -> Expression {
+        let expiry_timestamp = OffsetDateTime::now_utc().unix_timestamp() + seconds(ttl);
+        Expression {
+            update: format!(
+                "SET #lease_v=:new_lease_v, #expiry=:expiry REMOVE {HELD_EXPIRY_FIELD}"
+            ),
+            condition: "#lease_v IN (:lease_v, :new_lease_v)".into(),
+            names: [
+                ("#lease_v".into(), self.layout.lease_version.clone()),
+                ("#expiry".into(), self.layout.lease_expiry.clone()),
+            ]
+            .into(),
+            values: [
+                (
+                    ":new_lease_v".into(),
+                    AttributeValue::S(new_lease_v.to_string()),
+                ),
+                (":lease_v".into(), AttributeValue::S(lease_v.to_string())),
+                (
+                    ":expiry".into(),
+                    AttributeValue::N(expiry_timestamp.to_string()),
+                ),
+            ]
+            .into(),
+        }
+    }
+
+    /// Returns the expressions to release a held exclusive lease, see [`LeaseBackend::release_lease`].
+    fn release_expression(&self, lease_v: Uuid) -> Expression {
+        Expression {
+            update: format!("REMOVE #lease_v, #owner, {METADATA_FIELD}, {HELD_EXPIRY_FIELD}"),
+            condition: "#lease_v=:lease_v".into(),
+            names: [
+                ("#owner".into(), OWNER_FIELD.into()),
+                ("#lease_v".into(), self.layout.lease_version.clone()),
+            ]
+            .into(),
+            values: [(":lease_v".into(), AttributeValue::S(lease_v.to_string()))].into(),
+        }
+    }
+
+    /// Returns the expressions to add intent marker `id` to an ancestor, if the ancestor is
+    /// not held exclusively & already has an `intents` map. Also removes any expired exclusive lease.
+    ///
+    /// The ancestor's `lease_expiry` is only set if missing, as it may be later for other
+    /// intents, see [`DynamoDbBackend::raise_intents_expiry`].
+    fn put_intent_expression(&self, id: Uuid, lease: &PutLease) -> Expression {
+        let now = OffsetDateTime::now_utc();
+        let expiry_timestamp = now.unix_timestamp() + seconds(lease.ttl);
+        let expired_timestamp = now.unix_timestamp() - seconds(lease.clock_skew_margin);
+        Expression {
+            update: format!(
+                "SET {INTENTS_FIELD}.#intent=:expiry, #expiry=if_not_exists(#expiry, :expiry) \
+                REMOVE #lease_v, #owner, {METADATA_FIELD}, {HELD_EXPIRY_FIELD}"
+            ),
+            condition: format!("attribute_exists({INTENTS_FIELD}) AND {EXPIRED_CONDITION}"),
+            names: [
+                ("#intent".into(), id.to_string()),
+                ("#owner".into(), OWNER_FIELD.into()),
+                ("#lease_v".into(), self.layout.lease_version.clone()),
+                ("#expiry".into(), self.layout.lease_expiry.clone()),
+            ]
+            .into(),
+            values: [
+                (
+                    ":expiry".into(),
+                    AttributeValue::N(expiry_timestamp.to_string()),
+                ),
+                (
+                    ":expired".into(),
+                    AttributeValue::N(expired_timestamp.to_string()),
+                ),
+            ]
+            .into(),
+        }
+    }
+
+    /// Returns the expressions to extend intent marker `id` on an ancestor.
+    ///
+    /// The ancestor's `lease_expiry` is not lowered, see [`DynamoDbBackend::raise_intents_expiry`].
+    fn extend_intent_expression(&self, id: Uuid, ttl: Duration) -> Expression {
+        let expiry_timestamp = OffsetDateTime::now_utc().unix_timestamp() + seconds(ttl);
+        Expression {
+            update: format!("SET {INTENTS_FIELD}.#intent=:expiry"),
+            condition: format!("attribute_exists({INTENTS_FIELD}.#intent)"),
+            names: [("#intent".into(), id.to_string())].into(),
+            values: [(
+                ":expiry".into(),
+                AttributeValue::N(expiry_timestamp.to_string()),
+            )]
+            .into(),
+        }
+    }
+
+    /// Returns the expressions to remove intent marker `id` from an ancestor.
+    fn release_intent_expression(&self, id: Uuid) -> Expression {
+        Expression {
+            update: format!("REMOVE {INTENTS_FIELD}.#intent"),
+            condition: format!("attribute_exists({INTENTS_FIELD}.#intent)"),
+            names: [("#intent".into(), id.to_string())].into(),
+            values: <_>::default(),
+        }
+    }
+
+    /// Creates an empty `intents` map on the item for `key`, if missing, so intent
+    /// markers can be added.
+    ///
+    /// New items expire `ttl` from now, as would an intent marker, so are removed by ttl
+    /// if no marker is added.
+    async fn create_intents(&self, key: &str, ttl: Duration) -> Result<()> {
+        let expiry_timestamp = OffsetDateTime::now_utc().unix_timestamp() + seconds(ttl);
+        self.client
+            .update_item()
+            .table_name(&self.table_name)
+            .set_key(Some(self.item_key(key)))
+            .update_expression(format!(
+                "SET {INTENTS_FIELD}=if_not_exists({INTENTS_FIELD}, :empty), \
+                #expiry=if_not_exists(#expiry, :expiry)"
+            ))
+            .expression_attribute_names("#expiry", &self.layout.lease_expiry)
+            .expression_attribute_values(":empty", AttributeValue::M(<_>::default()))
+            .expression_attribute_values(":expiry", AttributeValue::N(expiry_timestamp.to_string()))
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    /// Raises the `lease_expiry` ttl of each ancestor to `ttl` from now, if earlier, so
+    /// ancestors are not removed while they have intent markers that expire later.
+    ///
+    /// A transaction cannot only raise the ttl, as an intent marker may have been put by
+    /// a client with a longer lease ttl, so this is a separate conditional update.
+    async fn raise_intents_expiry(&self, intents: &Intents, ttl: Duration) -> Result<()> {
+        let expiry_timestamp = OffsetDateTime::now_utc().unix_timestamp() + seconds(ttl);
+        for ancestor in &intents.ancestors {
+            let expr = Expression {
+                update: "SET #expiry=:expiry".into(),
+                condition: format!(
+                    "attribute_exists({INTENTS_FIELD}.#intent) AND #expiry < :expiry"
+                ),
+                names: [
+                    ("#intent".into(), intents.id.to_string()),
+                    ("#expiry".into(), self.layout.lease_expiry.clone()),
+                ]
+                .into(),
+                values: [(
+                    ":expiry".into(),
+                    AttributeValue::N(expiry_timestamp.to_string()),
+                )]
+                .into(),
+            };
+            // condition fails if already later
+            self.conditional(ancestor, expr).await?;
+        }
+        Ok(())
+    }
+
+    /// Puts a new exclusive lease & ancestor intent markers in a single transaction.
+    ///
+    /// Returns `Ok(Err(_))` if not put, with the cancellation reason of each item in
+    /// order, the lease first, if returned by the db.
+    async fn transact_put_hierarchical_lease(
+        &self,
+        lease: &PutLease,
+        intents: &Intents,
+    ) -> Result<Result<(), Vec<CancellationReason>>> {
+        let mut items =
+            vec![self.transact_update(&lease.key, self.put_lease_expression(lease, None))];
+        for ancestor in &intents.ancestors {
+            let expr = self.put_intent_expression(intents.id, lease);
+            items.push(self.transact_update(ancestor, expr));
+        }
+
+        match self
+            .client
+            .transact_write_items()
+            .set_transact_items(Some(items))
+            .send()
+            .await
+        {
+            Ok(_) => Ok(Ok(())),
+            Err(err) => match cancellation_reasons(&err) {
+                Some(reasons)
+                    if reasons.iter().any(|r| {
+                        matches!(
+                            r.code(),
+                            Some("ConditionalCheckFailed" | "TransactionConflict")
+                        )
+                    }) =>
+                {
+                    Ok(Err(reasons.to_vec()))
+                }
+                _ => Err(err.into()),
+            },
+        }
+    }
+
+    /// Removes shared lease holders & descendant intents from `item` that have expired
+    /// (including the clock skew margin).
+    ///
+    /// Returns `true` if all shared holders & intents were removed.
+    async fn remove_expired_holders(
+        &self,
+        key: &str,
+        item: &Item,
+        clock_skew_margin: Duration,
+    ) -> Result<bool> {
+        let expired_timestamp =
+            OffsetDateTime::now_utc().unix_timestamp() - seconds(clock_skew_margin);
+        let mut holders = 0;
+        let mut expired = vec![];
+        for field in [READERS_FIELD, INTENTS_FIELD] {
+            let Some(AttributeValue::M(map)) = item.get(field) else {
+                continue;
+            };
+            holders += map.len();
+            expired.extend(
+                map.iter()
+                    .filter(|(_, expiry)| {
+                        expiry
+                            .as_n()
+                            .ok()
+                            .and_then(|n| n.parse::<i64>().ok())
+                            .is_some_and(|expiry| expiry < expired_timestamp)
+                    })
+                    .map(|(id, expiry)| (field, id, expiry)),
+            );
+        }
+        if expired.is_empty() {
+            return Ok(false);
+        }
+
+        let mut remove = self
+            .client
+            .update_item()
+            .table_name(&self.table_name)
+            .set_key(Some(self.item_key(key)));
+        let mut paths = Vec::with_capacity(expired.len());
+        let mut conditions = Vec::with_capacity(expired.len());
+        for (idx, (field, id, expiry)) in expired.iter().enumerate() {
+            paths.push(format!("{field}.#r{idx}"));
+            // only remove if not extended in the meantime
+            conditions.push(format!("{field}.#r{idx} = :r{idx}"));
+            remove = remove
+                .expression_attribute_names(format!("#r{idx}"), *id)
+                .expression_attribute_values(format!(":r{idx}"), (*expiry).clone());
+        }
+        let remove = remove
+            .update_expression(format!("REMOVE {}", paths.join(", ")))
+            .condition_expression(conditions.join(" AND "))
+            .send()
+            .await;
+
+        match remove {
+            Err(err) => match condition_failed_item(&err) {
+                Some(_) => Ok(false),
+                None => Err(err.into()),
+            },
+            Ok(_) => Ok(expired.len() == holders),
+        }
+    }
+
+    /// Returns [`PutOutcome::Held`] with the time until the lease in `item` expires, if it is
+    /// held exclusively, shared or by descendant intents.
+    fn held(&self, item: Option<&Item>) -> PutOutcome {
+        let non_empty = |item: &Item, field| matches!(item.get(field), Some(AttributeValue::M(m)) if !m.is_empty());
+        let expires_in = item
+            .filter(|item| {
+                item.contains_key(&self.layout.lease_version)
+                    || non_empty(item, READERS_FIELD)
+                    || non_empty(item, INTENTS_FIELD)
+            })
+            .and_then(|item| self.held_expiry(item))
+            .and_then(|expiry| expiry.as_n().ok())
+            .and_then(|expiry| expiry.parse::<i64>().ok())
+            .map(|expiry| {
+                let secs = expiry - OffsetDateTime::now_utc().unix_timestamp();
+                Duration::from_secs(secs.try_into().unwrap_or_default())
+            });
+        PutOutcome::Held { expires_in }
+    }
+
+    /// Returns the expiry timestamp of the lease in `item`, from `held_expiry` if the
+    /// `lease_expiry` ttl has been raised to keep queued tickets.
+    fn held_expiry<'a>(&self, item: &'a Item) -> Option<&'a AttributeValue> {
+        item.get(HELD_EXPIRY_FIELD)
+            .or_else(|| item.get(&self.layout.lease_expiry))
+    }
+
+    /// Checks `name` is a S key of `key_type`.
+    fn check_key(
+        &self,
+        attrs: &[AttributeDefinition],
+        key_schema: &[KeySchemaElement],
+        name: &str,
+        key_type: KeyType,
+    ) -> Result<()> {
+        let described_kind = attrs
+            .iter()
+            .find(|attr| attr.attribute_name() == Some(name))
+            .ok_or_else(|| {
+                self.schema_mismatch(format!(
+                    "Missing attribute definition for {name}, available {:?}",
+                    vec(attrs.iter().filter_map(|a| a.attribute_name()))
+                ))
+            })?
+            .attribute_type()
+            .ok_or_else(|| self.schema_mismatch(format!("Missing attribute type for {name}")))?;
+        if described_kind != &ScalarAttributeType::S {
+            return Err(self.schema_mismatch(format!(
+                "Unexpected attribute type `{:?}` for {}, expected `{:?}`",
+                described_kind,
+                name,
+                ScalarAttributeType::S,
+            )));
+        }
+
+        let described_key_type = key_schema
+            .iter()
+            .find(|k| k.attribute_name() == Some(name))
+            .ok_or_else(|| {
+                self.schema_mismatch(format!(
+                    "Missing key schema for {name}, available {:?}",
+                    vec(key_schema.iter().filter_map(|k| k.attribute_name()))
+                ))
+            })?
+            .key_type()
+            .ok_or_else(|| self.schema_mismatch(format!("Missing key type for {name}")))?;
+        if described_key_type != &key_type {
+            return Err(self.schema_mismatch(format!(
+                "Unexpected key type `{:?}` for {}, expected `{:?}`",
+                described_key_type, name, key_type,
+            )));
+        }
+        Ok(())
+    }
+
+    fn schema_mismatch(&self, reason: String) -> Error {
+        Error::SchemaMismatch {
+            table_name: self.table_name.clone(),
+            reason,
+        }
+    }
+}
+
+#[async_trait]
+impl LeaseBackend for DynamoDbBackend {
+    /// Put a new exclusive lease into the db, incrementing the key's fence token.
+    ///
+    /// Succeeds if the lease is not held or has expired (including the clock skew margin).
+    #[instrument(skip_all)]
+    async fn put_lease(&self, lease: PutLease) -> Result<PutOutcome> {
+        self.put_exclusive_lease(&lease, None).await
+    }
+
+    /// Extends an active lease to `new_lease_v`.
+    ///
+    /// The lease may also already have `new_lease_v`, so retrying after an ambiguous
+    /// failure (e.g. a timeout after the db applied the update) will succeed.
+    #[instrument(skip_all)]
+    async fn extend_lease(
+        &self,
+        key: &str,
+        lease_v: Uuid,
+        new_lease_v: Uuid,
+        ttl: Duration,
+    ) -> Result<bool> {
+        self.conditional(key, self.extend_expression(lease_v, new_lease_v, ttl))
+            .await
+    }
+
+    /// Release the lease by removing its `lease_version` (& owner info), keeping the
+    /// item so the fence token is retained for the next lease.
+    #[instrument(skip_all)]
+    async fn release_lease(&self, key: &str, lease_v: Uuid) -> Result<bool> {
+        self.conditional(key, self.release_expression(lease_v))
+            .await
+    }
+
+    /// Put a new shared lease into the db, adding a new shared holder to the `readers` map.
+    ///
+    /// Succeeds if the lease is not held exclusively or has expired (including the clock skew margin).
+    #[instrument(skip_all)]
+    async fn put_shared_lease(&self, lease: PutLease) -> Result<PutOutcome> {
+        let now = OffsetDateTime::now_utc();
+        let expiry_timestamp = now.unix_timestamp() + seconds(lease.ttl);
+        let expired_timestamp = now.unix_timestamp() - seconds(lease.clock_skew_margin);
+        let reader_id = lease.lease_v.to_string();
+        let expiry_value = AttributeValue::N(expiry_timestamp.to_string());
+
+        // A shared holder is added to the `readers` map if it exists, otherwise the map
+        // is created. As the map may be created/removed concurrently try a few times.
+        let mut readers_exist = true;
+        for _ in 0..3 {
+            let mut put = self
+                .client
+                .update_item()
+                .table_name(&self.table_name)
+                .set_key(Some(self.item_key(&lease.key)))
+                .expression_attribute_names("#owner", OWNER_FIELD)
+                .expression_attribute_names("#lease_v", &self.layout.lease_version)
+                .expression_attribute_names("#expiry", &self.layout.lease_expiry)
+                .expression_attribute_values(":expiry", expiry_value.clone())
+                .expression_attribute_values(
+                    ":expired",
+                    AttributeValue::N(expired_timestamp.to_string()),
+                )
+                .return_values(ReturnValue::AllNew)
+                .return_values_on_condition_check_failure(
+                    ReturnValuesOnConditionCheckFailure::AllOld,
+                );
+            let (set_reader, readers_condition) = match readers_exist {
+                true => {
+                    put = put.expression_attribute_names("#reader", &reader_id);
+                    (
+                        format!("{READERS_FIELD}.#reader=:expiry"),
+                        format!("attribute_exists({READERS_FIELD})"),
+                    )
+                }
+                false => {
+                    put = put.expression_attribute_values(
+                        ":readers",
+                        AttributeValue::M([(reader_id.clone(), expiry_value.clone())].into()),
+                    );
+                    (
+                        format!("{READERS_FIELD}=:readers"),
+                        format!("attribute_not_exists({READERS_FIELD})"),
+                    )
+                }
+            };
+            let put = put
+                // also remove any expired exclusive lease
+                .update_expression(format!(
+                    "SET {set_reader}, #expiry=:expiry \
+                    REMOVE #lease_v, #owner, {METADATA_FIELD}, {HELD_EXPIRY_FIELD}"
+                ))
+                .condition_expression(format!("{readers_condition} AND {EXPIRED_CONDITION}"))
+                .send()
+                .await;
+
+            match put {
+                Ok(out) => {
+                    // shared leases do not increment the fence token
+                    let fence = out
+                        .attributes()
+                        .and_then(|attrs| attrs.get(FENCE_FIELD))
+                        .and_then(|fence| fence.as_n().ok())
+                        .and_then(|fence| fence.parse().ok())
+                        .unwrap_or_default();
+                    return Ok(PutOutcome::Acquired { fence });
+                }
+                Err(err) => match condition_failed_item(&err) {
+                    // if `readers` were as expected the condition failed due to an exclusive lease
+                    Some(Some(item)) if item.contains_key(READERS_FIELD) == readers_exist => {
+                        return Ok(self.held(Some(&item)));
+                    }
+                    Some(_) => readers_exist = !readers_exist,
+                    None => return Err(err.into()),
+                },
+            }
+        }
+        Ok(PutOutcome::Held { expires_in: None })
+    }
+
+    /// Put new exclusive leases into the db in a single transaction, then read the
+    /// new fence tokens, as transactions do not return updated values. If the read fails
+    /// the leases are released.
+    ///
+    /// If expired shared holders prevent this they are removed & the transaction retried once.
+    #[instrument(skip_all)]
+    async fn put_leases(&self, leases: Vec<PutLease>) -> Result<Option<Vec<u64>>> {
+        let keys: Vec<_> = leases.iter().map(|l| l.key.clone()).collect();
+        let lease_v = leases.first().map(|l| l.lease_v).unwrap_or_default();
+        let items = match self.transact_put_leases(&leases).await? {
+            Ok(()) => {
+                let release = self.release_leases(&keys, lease_v);
+                return self.put_fences(&keys, release).await.map(Some);
+            }
+            Err(items) => items,
+        };
+
+        let mut removed_readers = false;
+        for (lease, item) in leases.iter().zip(items) {
+            if let Some(item) = item {
+                removed_readers |= self
+                    .remove_expired_holders(&lease.key, &item, lease.clock_skew_margin)
+                    .await?;
+            }
+        }
+        if !removed_readers {
+            return Ok(None);
+        }
+        match self.transact_put_leases(&leases).await? {
+            Ok(()) => {
+                let release = self.release_leases(&keys, lease_v);
+                self.put_fences(&keys, release).await.map(Some)
+            }
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Extends active leases to `new_lease_v` in a single transaction, as
+    /// [`DynamoDbBackend::extend_lease`](LeaseBackend::extend_lease).
+    #[instrument(skip_all)]
+    async fn extend_leases(
+        &self,
+        keys: &[String],
+        lease_v: Uuid,
+        new_lease_v: Uuid,
+        ttl: Duration,
+    ) -> Result<bool> {
+        let expr = self.extend_expression(lease_v, new_lease_v, ttl);
+        let items = keys
+            .iter()
+            .map(|key| self.transact_update(key, expr.clone()))
+            .collect();
+        self.transact_conditional(items).await
+    }
+
+    /// Releases leases in a single transaction, as
+    /// [`DynamoDbBackend::release_lease`](LeaseBackend::release_lease).
+    #[instrument(skip_all)]
+    async fn release_leases(&self, keys: &[String], lease_v: Uuid) -> Result<bool> {
+        let expr = self.release_expression(lease_v);
+        let items = keys
+            .iter()
+            .map(|key| self.transact_update(key, expr.clone()))
+            .collect();
+        self.transact_conditional(items).await
+    }
+
+    /// Put a new exclusive lease & add intent markers to the `intents` map of each ancestor
+    /// in a single transaction, then read the new fence token. If the read fails the lease
+    /// & markers are released.
+    ///
+    /// Ancestors without an `intents` map have an empty map created & the transaction is
+    /// retried, as are leases blocked by expired shared holders or intents, up to 3 attempts.
+    #[instrument(skip_all)]
+    async fn put_hierarchical_lease(
+        &self,
+        lease: PutLease,
+        intents: &Intents,
+    ) -> Result<PutOutcome> {
+        for _ in 0..3 {
+            let reasons = match self
+                .transact_put_hierarchical_lease(&lease, intents)
+                .await?
+            {
+                Ok(()) => {
+                    let keys = [lease.key.clone()];
+                    let release =
+                        || self.release_hierarchical_lease(&lease.key, intents, lease.lease_v);
+                    if let Err(err) = self.raise_intents_expiry(intents, lease.ttl).await {
+                        if let Err(release_err) = release().await {
+                            tracing::warn!("Failed to release lease after failing to raise intents expiry: {release_err}");
+                        }
+                        return Err(err);
+                    }
+                    let fences = self.put_fences(&keys, release()).await?;
+                    let fence = fences.into_iter().next().ok_or_else(|| {
+                        Error::UnexpectedResponse(format!("missing {FENCE_FIELD}"))
+                    })?;
+                    return Ok(PutOutcome::Acquired { fence });
+                }
+                Err(reasons) => reasons,
+            };
+            let failed = |idx: usize| {
+                reasons
+                    .get(idx)
+                    .filter(|r| r.code() == Some("ConditionalCheckFailed"))
+            };
+
+            let mut retry = false;
+            if let Some(item) = failed(0).and_then(|r| r.item()) {
+                if !self
+                    .remove_expired_holders(&lease.key, item, lease.clock_skew_margin)
+                    .await?
+                {
+                    return Ok(self.held(Some(item)));
+                }
+                retry = true;
+            }
+            for (idx, ancestor) in intents.ancestors.iter().enumerate() {
+                match failed(idx + 1).map(|r| r.item()) {
+                    None => {}
+                    // the ancestor is held exclusively
+                    Some(Some(item)) if item.contains_key(INTENTS_FIELD) => {
+                        return Ok(self.held(Some(item)));
+                    }
+                    Some(_) => {
+                        self.create_intents(ancestor, lease.ttl).await?;
+                        retry = true;
+                    }
+                }
+            }
+            // otherwise a concurrent transaction conflicted
+            if !retry {
+                break;
+            }
+        }
+        Ok(PutOutcome::Held { expires_in: None })
+    }
+
+    /// Extends an active lease, as [`DynamoDbBackend::extend_lease`](LeaseBackend::extend_lease),
+    /// & its ancestor intent markers in a single transaction.
+    #[instrument(skip_all)]
+    async fn extend_hierarchical_lease(
+        &self,
+        key: &str,
+        intents: &Intents,
+        lease_v: Uuid,
+        new_lease_v: Uuid,
+        ttl: Duration,
+    ) -> Result<bool> {
+        let mut items =
+            vec![self.transact_update(key, self.extend_expression(lease_v, new_lease_v, ttl))];
+        for ancestor in &intents.ancestors {
+            items.push(
+                self.transact_update(ancestor, self.extend_intent_expression(intents.id, ttl)),
+            );
+        }
+        if !self.transact_conditional(items).await? {
+            return Ok(false);
+        }
+        // retried on error, the extension is idempotent
+        self.raise_intents_expiry(intents, ttl).await?;
+        Ok(true)
+    }
+
+    /// Releases a lease, as [`DynamoDbBackend::release_lease`](LeaseBackend::release_lease),
+    /// & removes its ancestor intent markers in a single transaction.
+    ///
+    /// If the lease or any marker was no longer held, each is released individually.
+    #[instrument(skip_all)]
+    async fn release_hierarchical_lease(
+        &self,
+        key: &str,
+        intents: &Intents,
+        lease_v: Uuid,
+    ) -> Result<bool> {
+        let mut items = vec![self.transact_update(key, self.release_expression(lease_v))];
+        for ancestor in &intents.ancestors {
+            items.push(self.transact_update(ancestor, self.release_intent_expression(intents.id)));
+        }
+        if self.transact_conditional(items).await? {
+            return Ok(true);
+        }
+
+        self.release_lease(key, lease_v).await?;
+        for ancestor in &intents.ancestors {
+            self.conditional(ancestor, self.release_intent_expression(intents.id))
+                .await?;
+        }
+        Ok(false)
+    }
+
+    #[instrument(skip_all)]
+    async fn extend_shared_lease(&self, key: &str, reader_id: Uuid, ttl: Duration) -> Result<bool> {
+        let expiry_timestamp = OffsetDateTime::now_utc().unix_timestamp() + seconds(ttl);
+
+        let update = self
+            .client
+            .update_item()
+            .table_name(&self.table_name)
+            .set_key(Some(self.item_key(key)))
+            .update_expression(format!(
+                "SET {READERS_FIELD}.#reader=:expiry, #expiry=:expiry"
+            ))
+            .expression_attribute_names("#expiry", &self.layout.lease_expiry)
+            .condition_expression(format!("attribute_exists({READERS_FIELD}.#reader)"))
+            .expression_attribute_names("#reader", reader_id.to_string())
+            .expression_attribute_values(":expiry", AttributeValue::N(expiry_timestamp.to_string()))
+            .send()
+            .await;
+
+        match update {
+            Err(err) => match condition_failed_item(&err) {
+                Some(_) => Ok(false),
+                None => Err(err.into()),
+            },
+            Ok(_) => Ok(true),
+        }
+    }
+
+    #[instrument(skip_all)]
+    async fn release_shared_lease(&self, key: &str, reader_id: Uuid) -> Result<bool> {
+        let release = self
+            .client
+            .update_item()
+            .table_name(&self.table_name)
+            .set_key(Some(self.item_key(key)))
+            .update_expression(format!("REMOVE {READERS_FIELD}.#reader"))
+            .condition_expression(format!("attribute_exists({READERS_FIELD}.#reader)"))
+            .expression_attribute_names("#reader", reader_id.to_string())
+            .send()
+            .await;
+
+        match release {
+            Err(err) => match condition_failed_item(&err) {
+                Some(_) => Ok(false),
+                None => Err(err.into()),
+            },
+            Ok(_) => Ok(true),
+        }
+    }
+
+    /// Appends a ticket to the `queue` list, ensuring the item does not expire, by ttl,
+    /// before the ticket.
+    #[instrument(skip_all)]
+    async fn enqueue(&self, key: &str, ticket: Uuid, ttl: Duration) -> Result<()> {
+        let expiry_timestamp = OffsetDateTime::now_utc().unix_timestamp() + seconds(ttl);
+        let expiry_value = AttributeValue::N(expiry_timestamp.to_string());
+        let entry = AttributeValue::M(
+            [
+                (TICKET_FIELD.into(), AttributeValue::S(ticket.to_string())),
+                (TICKET_EXPIRY_FIELD.into(), expiry_value.clone()),
+            ]
+            .into(),
+        );
+
+        // new items also need a ttl, so abandoned queues are eventually removed
+        let item = self
+            .client
+            .update_item()
+            .table_name(&self.table_name)
+            .set_key(Some(self.item_key(key)))
+            .update_expression(format!(
+                "SET {QUEUE_FIELD} = list_append(if_not_exists({QUEUE_FIELD}, :empty), :entry), \
+                #expiry = if_not_exists(#expiry, :expiry)"
+            ))
+            .expression_attribute_names("#expiry", &self.layout.lease_expiry)
+            .expression_attribute_values(":empty", AttributeValue::L(vec![]))
+            .expression_attribute_values(":entry", AttributeValue::L(vec![entry]))
+            .expression_attribute_values(":expiry", expiry_value)
+            .return_values(ReturnValue::AllNew)
+            .send()
+            .await?
+            .attributes
+            .unwrap_or_default();
+
+        self.keep_queued_item(key, &item, expiry_timestamp).await
+    }
+
+    /// Reads the `queue`, removing expired tickets ahead of `ticket`. If then at the
+    /// front of the queue puts the lease, otherwise refreshes the ticket expiry.
+    #[instrument(skip_all)]
+    async fn put_queued_lease(&self, lease: PutLease, ticket_id: Uuid) -> Result<PutOutcome> {
+        // stored tickets are compared as strings, malformed tickets just never match
+        let ticket = ticket_id.to_string();
+        let item = self
+            .client
+            .get_item()
+            .table_name(&self.table_name)
+            .set_key(Some(self.item_key(&lease.key)))
+            .consistent_read(true)
+            .send()
+            .await?
+            .item;
+        let held = self.held(item.as_ref());
+        let item = item.unwrap_or_default();
+        let queue = queue_tickets(item.clone());
+
+        let Some(mut position) = queue.iter().position(|(t, _)| *t == ticket) else {
+            // ticket expired & was removed, rejoin the queue
+            self.enqueue(&lease.key, ticket_id, lease.ttl).await?;
+            return Ok(held);
+        };
+
+        let expired_timestamp =
+            OffsetDateTime::now_utc().unix_timestamp() - seconds(lease.clock_skew_margin);
+        let expired: Vec<_> = queue[..position]
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, expiry))| *expiry < expired_timestamp)
+            .collect();
+        if !expired.is_empty() {
+            let mut remove = self
+                .client
+                .update_item()
+                .table_name(&self.table_name)
+                .set_key(Some(self.item_key(&lease.key)));
+            let mut paths = Vec::with_capacity(expired.len());
+            let mut conditions = Vec::with_capacity(expired.len());
+            for (idx, (t, _)) in &expired {
+                paths.push(format!("{QUEUE_FIELD}[{idx}]"));
+                // only remove if the queue has not changed in the meantime
+                conditions.push(format!("{QUEUE_FIELD}[{idx}].{TICKET_FIELD} = :t{idx}"));
+                remove = remove
+                    .expression_attribute_values(format!(":t{idx}"), AttributeValue::S(t.clone()));
+            }
+            let remove = remove
+                .update_expression(format!("REMOVE {}", paths.join(", ")))
+                .condition_expression(conditions.join(" AND "))
+                .send()
+                .await;
+            match remove {
+                Err(err) => match condition_failed_item(&err) {
+                    Some(_) => return Ok(held),
+                    None => return Err(err.into()),
+                },
+                Ok(_) => position -= expired.len(),
+            }
+        }
+
+        if position == 0 {
+            if let acquired @ PutOutcome::Acquired { .. } =
+                self.put_exclusive_lease(&lease, Some(&ticket)).await?
+            {
+                return Ok(acquired);
+            }
+        }
+
+        // keep the ticket alive while waiting
+        let expiry_timestamp = OffsetDateTime::now_utc().unix_timestamp() + seconds(lease.ttl);
+        let refresh = self
+            .client
+            .update_item()
+            .table_name(&self.table_name)
+            .set_key(Some(self.item_key(&lease.key)))
+            .update_expression(format!(
+                "SET {QUEUE_FIELD}[{position}].{TICKET_EXPIRY_FIELD} = :expiry"
+            ))
+            .condition_expression(format!(
+                "{QUEUE_FIELD}[{position}].{TICKET_FIELD} = :ticket"
+            ))
+            .expression_attribute_values(":expiry", AttributeValue::N(expiry_timestamp.to_string()))
+            .expression_attribute_values(":ticket", AttributeValue::S(ticket))
+            .send()
+            .await;
+        match refresh {
+            Err(err) => match condition_failed_item(&err) {
+                // queue changed, refreshed on the next attempt
+                Some(_) => Ok(held),
+                None => Err(err.into()),
+            },
+            Ok(_) => {
+                self.keep_queued_item(&lease.key, &item, expiry_timestamp)
+                    .await?;
+                Ok(held)
+            }
+        }
+    }
+
+    /// Removes a ticket from the `queue` list.
+    #[instrument(skip_all)]
+    async fn dequeue(&self, key: &str, ticket: Uuid) -> Result<()> {
+        let ticket = ticket.to_string();
+        // the position may change concurrently, so try a few times
+        for _ in 0..3 {
+            let queue = self
+                .client
+                .get_item()
+                .table_name(&self.table_name)
+                .set_key(Some(self.item_key(key)))
+                .consistent_read(true)
+                .send()
+                .await?
+                .item
+                .map(queue_tickets)
+                .unwrap_or_default();
+            let Some(position) = queue.iter().position(|(t, _)| *t == ticket) else {
+                return Ok(());
+            };
+
+            let remove = self
+                .client
+                .update_item()
+                .table_name(&self.table_name)
+                .set_key(Some(self.item_key(key)))
+                .update_expression(format!("REMOVE {QUEUE_FIELD}[{position}]"))
+                .condition_expression(format!(
+                    "{QUEUE_FIELD}[{position}].{TICKET_FIELD} = :ticket"
+                ))
+                .expression_attribute_values(":ticket", AttributeValue::S(ticket.clone()))
+                .send()
+                .await;
+            match remove {
+                Err(err) => match condition_failed_item(&err) {
+                    Some(_) => continue,
+                    None => return Err(err.into()),
+                },
+                Ok(_) => return Ok(()),
+            }
+        }
+        // left to expire
+        Ok(())
+    }
+
+    /// Waits for a change from the lease table's stream, if enabled with
+    /// [`DynamoDbBackend::with_streams`].
+    #[cfg(feature = "streams")]
+    async fn wait_for_change(&self, key: &str, max_wait: Duration) {
+        match &self.streams {
+            Some(streams) => {
+                streams
+                    .wait_for_change(self.item_key(key), &self.layout.lease_version, max_wait)
+                    .await
+            }
+            None => tokio::time::sleep(max_wait).await,
+        }
+    }
+
+    #[instrument(skip_all)]
+    async fn get_lease(&self, key: &str) -> Result<Option<LeaseInfo>> {
+        let item = self
+            .client
+            .get_item()
+            .table_name(&self.table_name)
+            .set_key(Some(self.item_key(key)))
+            .consistent_read(true)
+            .send()
+            .await?
+            .item;
+
+        match item {
+            Some(item) => lease_info(&self.layout, item, OffsetDateTime::now_utc()),
+            None => Ok(None),
+        }
+    }
+
+    fn list_leases(&self, key_prefix: Option<String>) -> LeaseStream {
+        let mut filter = "attribute_exists(#lease_v)".to_owned();
+        let mut scan = self
+            .client
+            .scan()
+            .table_name(&self.table_name)
+            .consistent_read(true)
+            .expression_attribute_names("#lease_v", &self.layout.lease_version);
+        if let Some(prefix) = key_prefix {
+            filter += " AND begins_with(#key, :prefix)";
+            scan = scan
+                // "key" is a reserved word
+                .expression_attribute_names("#key", &self.layout.key)
+                .expression_attribute_values(":prefix", AttributeValue::S(prefix));
+        }
+        // skip other items sharing the table
+        if let Some((name, SortKey::Fixed(value))) = &self.layout.sort_key {
+            filter += " AND #sort_key = :sort_key";
+            scan = scan
+                .expression_attribute_names("#sort_key", name)
+                .expression_attribute_values(":sort_key", AttributeValue::S(value.clone()));
+        }
+
+        let now = OffsetDateTime::now_utc();
+        let layout = self.layout.clone();
+        let leases = scan
+            .filter_expression(filter)
+            .into_paginator()
+            .items()
+            .send()
+            .filter_map(move |item| match item {
+                Ok(item) => lease_info(&layout, item, now).transpose(),
+                Err(err) => Some(Err(err.into())),
+            });
+        Box::pin(leases)
+    }
+
+    /// Checks table is active & has a valid schema.
+    ///
+    /// Only the `key` hash key, optional range key & `lease_expiry` ttl, as named by the
+    /// [`TableLayout`], are part of the table definition.
+    /// Other fields, like `lease_version`, `fence` & `owner`, are schemaless so need no checks.
+    #[instrument(skip_all)]
+    async fn validate(&self) -> Result<()> {
+        // fetch table & ttl descriptions concurrently
+        let (table_desc, ttl_desc) = tokio::join!(
+            self.client
+                .describe_table()
+                .table_name(&self.table_name)
+                .send(),
+            self.client
+                .describe_time_to_live()
+                .table_name(&self.table_name)
+                .send()
+        );
+
+        let desc = match table_desc {
+            Err(SdkError::ServiceError(se))
+                if matches!(se.err(), DescribeTableError::ResourceNotFoundException(..)) =>
+            {
+                return Err(Error::MissingTable {
+                    table_name: self.table_name.clone(),
+                    source: Box::new(SdkError::ServiceError(se).into()),
+                });
+            }
+            table_desc => table_desc?
+                .table
+                .ok_or_else(|| Error::UnexpectedResponse("no table description".into()))?,
+        };
+
+        // check the key schema matches the layout, with S keys
+        let attrs = desc.attribute_definitions.unwrap_or_default();
+        let key_schema = desc.key_schema.unwrap_or_default();
+        let mut expected_keys = vec![(self.layout.key.as_str(), KeyType::Hash)];
+        if let Some((name, _)) = &self.layout.sort_key {
+            expected_keys.push((name, KeyType::Range));
+        }
+        if key_schema.len() != expected_keys.len() {
+            return Err(self.schema_mismatch(format!(
+                "Unexpected number of keys ({}) in key_schema, expected {}. Got {:?}",
+                key_schema.len(),
+                expected_keys.len(),
+                vec(key_schema.iter().map(|k| k.attribute_name().unwrap_or("?"))),
+            )));
+        }
+        for (name, key_type) in expected_keys {
+            self.check_key(&attrs, &key_schema, name, key_type)?;
+        }
+
+        // check "lease_expiry" is a ttl field
+        let update_time_to_live_desc = ttl_desc?
+            .time_to_live_description
+            .ok_or_else(|| Error::UnexpectedResponse("no time to live description".into()))?;
+
+        let lease_expiry = &self.layout.lease_expiry;
+        if update_time_to_live_desc.attribute_name() != Some(lease_expiry) {
+            return Err(self.schema_mismatch(format!("time to live for {lease_expiry} is not set")));
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the cancellation reasons, in transaction item order, if `err` is a cancelled transaction.
+fn cancellation_reasons<R>(
+    err: &SdkError<TransactWriteItemsError, R>,
+) -> Option<&[CancellationReason]> {
+    match err {
+        SdkError::ServiceError(se) => match se.err() {
+            TransactWriteItemsError::TransactionCanceledException(e) => {
+                Some(e.cancellation_reasons().unwrap_or_default())
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Returns `Some` if `err` is a conditional check failure, with the existing item if returned.
+fn condition_failed_item<R>(err: &SdkError<UpdateItemError, R>) -> Option<Option<Item>> {
+    match err {
+        SdkError::ServiceError(se) => match se.err() {
+            UpdateItemError::ConditionalCheckFailedException(e) => Some(e.item().cloned()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Parses the `queue` of an item into tickets & expiry timestamps.
+fn queue_tickets(mut item: Item) -> Vec<(String, i64)> {
+    let queue = match item.remove(QUEUE_FIELD) {
+        Some(AttributeValue::L(queue)) => queue,
+        _ => return vec![],
+    };
+    queue
+        .into_iter()
+        .map(|entry| {
+            let mut entry = match entry {
+                AttributeValue::M(entry) => entry,
+                _ => <_>::default(),
+            };
+            let ticket = match entry.remove(TICKET_FIELD) {
+                Some(AttributeValue::S(ticket)) => ticket,
+                _ => String::new(),
+            };
+            let expiry = entry
+                .get(TICKET_EXPIRY_FIELD)
+                .and_then(|n| n.as_n().ok())
+                .and_then(|n| n.parse().ok())
+                // invalid entries are treated as expired
+                .unwrap_or(i64::MIN);
+            (ticket, expiry)
+        })
+        .collect()
+}
+
+/// Parses a lease table item into [`LeaseInfo`]. Returns `None` if not held.
+fn lease_info(
+    layout: &TableLayout,
+    mut item: Item,
+    now: OffsetDateTime,
+) -> Result<Option<LeaseInfo>> {
+    let invalid = |field: &str| Error::UnexpectedResponse(format!("missing or invalid {field}"));
+
+    let lease_version = match item.remove(&layout.lease_version) {
+        Some(AttributeValue::S(lease_v)) => lease_v,
+        None => return Ok(None),
+        Some(_) => return Err(invalid(&layout.lease_version)),
+    };
+    let key = match item.remove(&layout.key) {
+        Some(AttributeValue::S(key)) => key,
+        _ => return Err(invalid(&layout.key)),
+    };
+    let expiry = item
+        .get(HELD_EXPIRY_FIELD)
+        .or_else(|| item.get(&layout.lease_expiry));
+    let expiry = match expiry.map(|v| v.as_n()) {
+        Some(Ok(n)) => n
+            .parse()
+            .ok()
+            .and_then(|ts| OffsetDateTime::from_unix_timestamp(ts).ok())
+            .ok_or_else(|| invalid(&layout.lease_expiry))?,
+        _ => return Err(invalid(&layout.lease_expiry)),
+    };
+    let fence_token = match item.get(FENCE_FIELD).map(|v| v.as_n()) {
+        Some(Ok(n)) => n.parse().map_err(|_| invalid(FENCE_FIELD))?,
+        // written by a client predating fencing
+        None => 0,
+        Some(Err(_)) => return Err(invalid(FENCE_FIELD)),
+    };
+    let owner_id = match item.remove(OWNER_FIELD) {
+        Some(AttributeValue::S(owner)) => Some(owner),
+        _ => None,
+    };
+    let metadata = match item.remove(METADATA_FIELD) {
+        Some(AttributeValue::M(metadata)) => metadata
+            .into_iter()
+            .filter_map(|(k, v)| match v {
+                AttributeValue::S(v) => Some((k, v)),
+                _ => None,
+            })
+            .collect(),
+        _ => <_>::default(),
+    };
+
+    Ok(Some(LeaseInfo {
+        key,
+        expiry,
+        expired: expiry <= now,
+        lease_version,
+        fence_token,
+        owner_id,
+        metadata,
+    }))
+}
+
+#[inline]
+fn vec<T>(iter: impl Iterator<Item = T>) -> Vec<T> {
+    iter.collect()
+}
+
+/// Returns whole seconds of `duration`.
+fn seconds(duration: Duration) -> i64 {
+    duration.as_secs().try_into().unwrap_or(i64::MAX)
+}
@@ -0,0 +1,103 @@
+use std::{fmt, sync::Arc};
+
+/// Lease table key schema & attribute names, see [`DynamoDbBackend::with_layout`](super::DynamoDbBackend::with_layout).
+///
+/// The default layout uses a `key` hash key, `lease_expiry` ttl attribute & `lease_version`.
+/// Other attribute names, e.g. `fence` & `owner`, are not configurable.
+///
+/// # Example
+/// ```
+/// use dynamodb_lease::backend::{SortKey, TableLayout};
+///
+/// // single-table design with `pk`/`sk` keys & a `ttl` attribute
+/// let layout = TableLayout::default()
+///     .key_attribute("pk")
+///     .sort_key("sk", SortKey::Fixed("lease".into()))
+///     .lease_expiry_attribute("ttl");
+/// ```
+#[derive(Debug, Clone)]
+pub struct TableLayout {
+    pub(super) key: String,
+    pub(super) sort_key: Option<(String, SortKey)>,
+    pub(super) lease_expiry: String,
+    pub(super) lease_version: String,
+}
+
+impl Default for TableLayout {
+    fn default() -> Self {
+        Self {
+            key: "key".into(),
+            sort_key: None,
+            lease_expiry: "lease_expiry".into(),
+            lease_version: "lease_version".into(),
+        }
+    }
+}
+
+impl TableLayout {
+    /// Sets the hash key attribute name, of type S, set to the lease key.
+    ///
+    /// Default `"key"`.
+    pub fn key_attribute(mut self, name: impl Into<String>) -> Self {
+        self.key = name.into();
+        self
+    }
+
+    /// Sets a range key attribute name, of type S, & the value to use for each lease key.
+    ///
+    /// Default none, i.e. the table only has a hash key.
+    pub fn sort_key(mut self, name: impl Into<String>, value: SortKey) -> Self {
+        self.sort_key = Some((name.into(), value));
+        self
+    }
+
+    /// Sets the lease expiry attribute name, which must be the table's ttl attribute.
+    ///
+    /// Default `"lease_expiry"`.
+    pub fn lease_expiry_attribute(mut self, name: impl Into<String>) -> Self {
+        self.lease_expiry = name.into();
+        self
+    }
+
+    /// Sets the lease version attribute name.
+    ///
+    /// Default `"lease_version"`.
+    pub fn lease_version_attribute(mut self, name: impl Into<String>) -> Self {
+        self.lease_version = name.into();
+        self
+    }
+}
+
+/// Range key value for a lease key, see [`TableLayout::sort_key`].
+#[derive(Clone)]
+#[non_exhaustive]
+pub enum SortKey {
+    /// The same value for every lease.
+    Fixed(String),
+    /// A value derived from the lease key.
+    Derived(Arc<dyn Fn(&str) -> String + Send + Sync>),
+}
+
+impl SortKey {
+    /// Returns a [`SortKey::Derived`] using `f`.
+    pub fn derived(f: impl Fn(&str) -> String + Send + Sync + 'static) -> Self {
+        Self::Derived(Arc::new(f))
+    }
+
+    /// Returns the range key value for the lease `key`.
+    pub(super) fn value(&self, key: &str) -> String {
+        match self {
+            Self::Fixed(value) => value.clone(),
+            Self::Derived(f) => f(key),
+        }
+    }
+}
+
+impl fmt::Debug for SortKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Fixed(value) => f.debug_tuple("Fixed").field(value).finish(),
+            Self::Derived(_) => f.write_str("Derived(..)"),
+        }
+    }
+}
@@ -0,0 +1,290 @@
+use super::{Item, INTENTS_FIELD, READERS_FIELD};
+use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_dynamodbstreams::types::{
+    AttributeValue as StreamAttributeValue, OperationType, ShardIteratorType,
+};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Weak,
+    },
+    time::Duration,
+};
+use tokio::sync::broadcast;
+
+/// How often each open stream shard is polled for records.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Watches the lease table's dynamodb stream for changed items.
+///
+/// The stream is only polled while there are waiters.
+#[derive(Debug, Clone)]
+pub(super) struct StreamWatcher {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    streams: aws_sdk_dynamodbstreams::Client,
+    dynamodb: aws_sdk_dynamodb::Client,
+    table_name: String,
+    /// Modified or removed items.
+    changes: broadcast::Sender<Arc<Change>>,
+    /// Set while the background watch task is running.
+    watching: AtomicBool,
+}
+
+impl StreamWatcher {
+    pub(super) fn new(
+        streams: aws_sdk_dynamodbstreams::Client,
+        dynamodb: aws_sdk_dynamodb::Client,
+        table_name: String,
+    ) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                streams,
+                dynamodb,
+                table_name,
+                changes: broadcast::channel(64).0,
+                watching: AtomicBool::new(false),
+            }),
+        }
+    }
+
+    /// Waits until the item with primary key `item_key` may have been released, see
+    /// [`Change::may_release`], or `max_wait` elapses.
+    pub(super) async fn wait_for_change(
+        &self,
+        item_key: Item,
+        lease_version: &str,
+        max_wait: Duration,
+    ) {
+        let mut changes = self.inner.changes.subscribe();
+        if !self.inner.watching.swap(true, Ordering::SeqCst) {
+            tokio::spawn(watch(Arc::downgrade(&self.inner)));
+        }
+
+        let _ = tokio::time::timeout(max_wait, async {
+            loop {
+                match changes.recv().await {
+                    Ok(change)
+                        if change.item_key == item_key && change.may_release(lease_version) =>
+                    {
+                        break
+                    }
+                    Ok(_) => {}
+                    // missed some changes, which may include `key`
+                    Err(broadcast::error::RecvError::Lagged(_)) => break,
+                    Err(broadcast::error::RecvError::Closed) => std::future::pending().await,
+                }
+            }
+        })
+        .await;
+    }
+}
+
+/// An item modified or removed, read from a stream record.
+#[derive(Debug)]
+struct Change {
+    /// Primary key of the item.
+    item_key: Item,
+    /// The item before the change, if included by the stream view type.
+    old_image: Option<HashMap<String, StreamAttributeValue>>,
+    /// The item after the change, if included by the stream view type & not removed.
+    new_image: Option<HashMap<String, StreamAttributeValue>>,
+}
+
+impl Change {
+    /// Returns `true` if the change may have released a lease, allowing waiters to acquire,
+    /// i.e. the item was removed, or its `lease_version`, a shared holder or an intent
+    /// marker was removed.
+    ///
+    /// Other changes, e.g. extensions & fair queue tickets, do not wake waiters. Without
+    /// both images, i.e. a stream view type other than `NEW_AND_OLD_IMAGES`, every change may.
+    fn may_release(&self, lease_version: &str) -> bool {
+        let (Some(old), Some(new)) = (&self.old_image, &self.new_image) else {
+            return true;
+        };
+        if old.contains_key(lease_version) && !new.contains_key(lease_version) {
+            return true;
+        }
+        [READERS_FIELD, INTENTS_FIELD].into_iter().any(|field| {
+            let new_holders = new.get(field).and_then(|m| m.as_m().ok());
+            old.get(field)
+                .and_then(|m| m.as_m().ok())
+                .is_some_and(|old_holders| {
+                    old_holders
+                        .keys()
+                        .any(|id| !new_holders.is_some_and(|new| new.contains_key(id)))
+                })
+        })
+    }
+}
+
+/// Polls the stream sending changed items, while there are waiters.
+async fn watch(inner: Weak<Inner>) {
+    let stream_arn = match inner.upgrade() {
+        Some(inner) => match latest_stream_arn(&inner).await {
+            Some(arn) => arn,
+            None => {
+                inner.watching.store(false, Ordering::SeqCst);
+                return;
+            }
+        },
+        None => return,
+    };
+
+    // open shard id -> shard iterator
+    let mut iterators = HashMap::new();
+    let mut refresh_shards = true;
+    loop {
+        let Some(inner) = inner.upgrade() else { return };
+        if inner.changes.receiver_count() == 0 {
+            inner.watching.store(false, Ordering::SeqCst);
+            return;
+        }
+
+        if refresh_shards {
+            refresh_shards = false;
+            if let Err(err) = add_open_shards(&inner, &stream_arn, &mut iterators).await {
+                tracing::warn!(table = inner.table_name, "Failed to describe stream: {err}");
+                refresh_shards = true;
+            }
+        }
+
+        for (shard_id, iterator) in iterators.clone() {
+            let records = inner
+                .streams
+                .get_records()
+                .shard_iterator(iterator)
+                .send()
+                .await;
+            let records = match records {
+                Ok(records) => records,
+                Err(err) => {
+                    // e.g. an expired iterator, start again from the latest record
+                    tracing::warn!(shard_id, "Failed to get stream records: {err}");
+                    iterators.remove(&shard_id);
+                    refresh_shards = true;
+                    continue;
+                }
+            };
+
+            for record in records.records().unwrap_or_default() {
+                if !matches!(
+                    record.event_name(),
+                    Some(OperationType::Modify | OperationType::Remove)
+                ) {
+                    continue;
+                }
+                let Some(stream_record) = record.dynamodb() else {
+                    continue;
+                };
+                let Some(keys) = stream_record.keys() else {
+                    continue;
+                };
+                // lease tables only have S keys
+                let item_key = keys
+                    .iter()
+                    .filter_map(|(name, value)| {
+                        Some((name.clone(), AttributeValue::S(value.as_s().ok()?.clone())))
+                    })
+                    .collect();
+                let _ = inner.changes.send(Arc::new(Change {
+                    item_key,
+                    old_image: stream_record.old_image().cloned(),
+                    new_image: stream_record.new_image().cloned(),
+                }));
+            }
+
+            match records.next_shard_iterator() {
+                Some(next) => {
+                    iterators.insert(shard_id, next.into());
+                }
+                // shard closed, new shards will have been opened
+                None => {
+                    iterators.remove(&shard_id);
+                    refresh_shards = true;
+                }
+            }
+        }
+
+        drop(inner);
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// Returns the table's stream arn, if streams are enabled.
+async fn latest_stream_arn(inner: &Inner) -> Option<String> {
+    let desc = inner
+        .dynamodb
+        .describe_table()
+        .table_name(&inner.table_name)
+        .send()
+        .await;
+    match desc {
+        Ok(desc) => {
+            let arn = desc.table().and_then(|t| t.latest_stream_arn());
+            if arn.is_none() {
+                tracing::warn!(
+                    table = inner.table_name,
+                    "Streams not enabled for lease table, polling instead"
+                );
+            }
+            arn.map(String::from)
+        }
+        Err(err) => {
+            tracing::warn!(table = inner.table_name, "Failed to describe table: {err}");
+            None
+        }
+    }
+}
+
+/// Adds iterators, starting from the latest record, for open shards not in `iterators`.
+async fn add_open_shards(
+    inner: &Inner,
+    stream_arn: &str,
+    iterators: &mut HashMap<String, String>,
+) -> Result<(), aws_sdk_dynamodbstreams::Error> {
+    let mut start_shard_id = None;
+    loop {
+        let desc = inner
+            .streams
+            .describe_stream()
+            .stream_arn(stream_arn)
+            .set_exclusive_start_shard_id(start_shard_id.take())
+            .send()
+            .await?;
+        let Some(desc) = desc.stream_description() else {
+            return Ok(());
+        };
+
+        let open_shards = desc.shards().unwrap_or_default().iter().filter(|shard| {
+            shard
+                .sequence_number_range()
+                .is_some_and(|range| range.ending_sequence_number().is_none())
+        });
+        for shard_id in open_shards.filter_map(|s| s.shard_id()) {
+            if iterators.contains_key(shard_id) {
+                continue;
+            }
+            let iterator = inner
+                .streams
+                .get_shard_iterator()
+                .stream_arn(stream_arn)
+                .shard_id(shard_id)
+                .shard_iterator_type(ShardIteratorType::Latest)
+                .send()
+                .await?;
+            if let Some(iterator) = iterator.shard_iterator() {
+                iterators.insert(shard_id.into(), iterator.into());
+            }
+        }
+
+        match desc.last_evaluated_shard_id() {
+            Some(last) => start_shard_id = Some(last.into()),
+            None => return Ok(()),
+        }
+    }
+}
@@ -0,0 +1,273 @@
+use super::DynamoDbBackend;
+use crate::{Error, Result};
+use aws_sdk_dynamodb::{
+    error::{ProvideErrorMetadata, SdkError},
+    operation::create_table::CreateTableError,
+    types::{
+        AttributeDefinition, BillingMode, KeySchemaElement, KeyType,
+        PointInTimeRecoverySpecification, ProvisionedThroughput, ScalarAttributeType,
+        SseSpecification, SseType, StreamSpecification, StreamViewType, TableStatus, Tag,
+        TimeToLiveSpecification, TimeToLiveStatus,
+    },
+};
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::instrument;
+
+/// How often the table status is polled while waiting for it to become active.
+const ACTIVE_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Lease table configuration used to create the table if it does not exist,
+/// see [`DynamoDbBackend::create_table_if_missing`].
+///
+/// # Example
+/// ```
+/// # use std::time::Duration;
+/// # use dynamodb_lease::backend::TableConfig;
+/// let config = TableConfig::default()
+///     .provisioned_throughput(5, 5)
+///     .tag("team", "platform")
+///     .point_in_time_recovery(true);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableConfig {
+    /// `None` for on-demand (pay per request) billing.
+    throughput: Option<(i64, i64)>,
+    tags: Vec<(String, String)>,
+    sse: Option<Sse>,
+    point_in_time_recovery: bool,
+    stream: bool,
+    active_timeout: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Sse {
+    /// AWS owned key.
+    Owned,
+    /// KMS key, `None` for the AWS managed key.
+    Kms(Option<String>),
+}
+
+impl Default for TableConfig {
+    fn default() -> Self {
+        Self {
+            throughput: None,
+            tags: vec![],
+            sse: None,
+            point_in_time_recovery: false,
+            stream: false,
+            active_timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+impl TableConfig {
+    /// Use provisioned billing with the given read & write capacity units.
+    ///
+    /// Default on-demand, pay per request, billing.
+    pub fn provisioned_throughput(mut self, read_capacity: i64, write_capacity: i64) -> Self {
+        self.throughput = Some((read_capacity, write_capacity));
+        self
+    }
+
+    /// Adds a tag to the table.
+    pub fn tag(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.tags.push((key.into(), value.into()));
+        self
+    }
+
+    /// Uses server-side encryption with an AWS owned key, dynamodb's default encryption,
+    /// instead of a KMS key set by [`TableConfig::sse_kms`].
+    ///
+    /// Default AWS owned key.
+    pub fn sse(mut self) -> Self {
+        self.sse = Some(Sse::Owned);
+        self
+    }
+
+    /// Uses server-side encryption with a KMS key, or the AWS managed KMS key,
+    /// `alias/aws/dynamodb`, if `None`.
+    pub fn sse_kms(mut self, kms_key_id: Option<String>) -> Self {
+        self.sse = Some(Sse::Kms(kms_key_id));
+        self
+    }
+
+    /// Sets whether point-in-time recovery is enabled for the table.
+    ///
+    /// Default `false`.
+    pub fn point_in_time_recovery(mut self, enabled: bool) -> Self {
+        self.point_in_time_recovery = enabled;
+        self
+    }
+
+    /// Sets whether a dynamodb stream, with new & old images, is enabled for the table,
+    /// used to wait for leases to be released with feature `streams`.
+    ///
+    /// Default `false`.
+    pub fn stream(mut self, enabled: bool) -> Self {
+        self.stream = enabled;
+        self
+    }
+
+    /// Sets the max wait for a new table to become active, after which
+    /// [`Error::TableNotActive`] is returned.
+    ///
+    /// Default `60s`.
+    pub fn active_timeout(mut self, timeout: Duration) -> Self {
+        self.active_timeout = timeout;
+        self
+    }
+}
+
+impl DynamoDbBackend {
+    /// Creates the lease table with the `key` hash key, & range key if configured, if it does
+    /// not exist, waits for it to become active & enables ttl on `lease_expiry`.
+    ///
+    /// Attribute names are from the backend's [`TableLayout`](super::TableLayout).
+    ///
+    /// An existing table is not changed, other than enabling ttl & point-in-time recovery
+    /// if configured. Use [`LeaseBackend::validate`](crate::backend::LeaseBackend::validate)
+    /// to check its schema.
+    #[instrument(skip_all)]
+    pub async fn create_table_if_missing(&self, config: &TableConfig) -> Result<()> {
+        let mut keys = vec![(self.layout.key.as_str(), KeyType::Hash)];
+        if let Some((name, _)) = &self.layout.sort_key {
+            keys.push((name, KeyType::Range));
+        }
+        let mut create = self.client.create_table().table_name(&self.table_name);
+        for (name, key_type) in keys {
+            create = create
+                .attribute_definitions(
+                    AttributeDefinition::builder()
+                        .attribute_name(name)
+                        .attribute_type(ScalarAttributeType::S)
+                        .build(),
+                )
+                .key_schema(
+                    KeySchemaElement::builder()
+                        .attribute_name(name)
+                        .key_type(key_type)
+                        .build(),
+                );
+        }
+        create = match config.throughput {
+            Some((read, write)) => create
+                .billing_mode(BillingMode::Provisioned)
+                .provisioned_throughput(
+                    ProvisionedThroughput::builder()
+                        .read_capacity_units(read)
+                        .write_capacity_units(write)
+                        .build(),
+                ),
+            None => create.billing_mode(BillingMode::PayPerRequest),
+        };
+        for (key, value) in &config.tags {
+            create = create.tags(Tag::builder().key(key).value(value).build());
+        }
+        if let Some(sse) = &config.sse {
+            // enabled means KMS encryption, disabled the AWS owned key
+            create = create.sse_specification(match sse {
+                Sse::Owned => SseSpecification::builder().enabled(false).build(),
+                Sse::Kms(key_id) => SseSpecification::builder()
+                    .enabled(true)
+                    .sse_type(SseType::Kms)
+                    .set_kms_master_key_id(key_id.clone())
+                    .build(),
+            });
+        }
+        if config.stream {
+            create = create.stream_specification(
+                StreamSpecification::builder()
+                    .stream_enabled(true)
+                    .stream_view_type(StreamViewType::NewAndOldImages)
+                    .build(),
+            );
+        }
+
+        match create.send().await {
+            Ok(_) => tracing::info!(table = self.table_name, "Created lease table"),
+            Err(SdkError::ServiceError(se))
+                if matches!(se.err(), CreateTableError::ResourceInUseException(..)) => {}
+            Err(err) => return Err(err.into()),
+        }
+
+        self.wait_for_active(config.active_timeout).await?;
+        self.enable_ttl().await?;
+        if config.point_in_time_recovery {
+            self.client
+                .update_continuous_backups()
+                .table_name(&self.table_name)
+                .point_in_time_recovery_specification(
+                    PointInTimeRecoverySpecification::builder()
+                        .point_in_time_recovery_enabled(true)
+                        .build(),
+                )
+                .send()
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Waits for the table to be active, e.g. after creation.
+    async fn wait_for_active(&self, timeout: Duration) -> Result<()> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let status = self
+                .client
+                .describe_table()
+                .table_name(&self.table_name)
+                .send()
+                .await?
+                .table
+                .and_then(|t| t.table_status);
+            if status == Some(TableStatus::Active) {
+                return Ok(());
+            }
+            if Instant::now() + ACTIVE_POLL_INTERVAL > deadline {
+                return Err(Error::TableNotActive(timeout));
+            }
+            tokio::time::sleep(ACTIVE_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Enables ttl on the `lease_expiry` attribute, if not already enabled.
+    async fn enable_ttl(&self) -> Result<()> {
+        let ttl = self
+            .client
+            .describe_time_to_live()
+            .table_name(&self.table_name)
+            .send()
+            .await?;
+        if matches!(
+            ttl.time_to_live_description()
+                .and_then(|d| d.time_to_live_status()),
+            Some(TimeToLiveStatus::Enabled | TimeToLiveStatus::Enabling)
+        ) {
+            return Ok(());
+        }
+
+        let update = self
+            .client
+            .update_time_to_live()
+            .table_name(&self.table_name)
+            .time_to_live_specification(
+                TimeToLiveSpecification::builder()
+                    .enabled(true)
+                    .attribute_name(&self.layout.lease_expiry)
+                    .build(),
+            )
+            .send()
+            .await;
+        match update {
+            Ok(_) => Ok(()),
+            // enabled concurrently
+            Err(SdkError::ServiceError(se))
+                if se.err().code() == Some("ValidationException")
+                    && se.err().message() == Some("TimeToLive is already enabled") =>
+            {
+                Ok(())
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+}
@@ -0,0 +1,343 @@
+use crate::{
+    backend::{Intents, LeaseBackend, LeaseStream, PutLease, PutOutcome},
+    Error, LeaseInfo, Result,
+};
+use async_trait::async_trait;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use uuid::Uuid;
+
+/// A [`LeaseBackend`] operation, see [`FaultyBackend`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Operation {
+    /// Putting new exclusive or shared leases.
+    Acquire,
+    /// Extending held leases.
+    Extend,
+    /// Releasing held leases.
+    Release,
+    /// Reading a held lease, see [`Client::describe`](crate::Client::describe).
+    Describe,
+    /// Joining a fair queue, see [`ClientBuilder::fair`](crate::ClientBuilder::fair).
+    ///
+    /// Faults are injected *after* the inner backend writes the ticket, simulating
+    /// a slow or failed response to a write that was applied.
+    Enqueue,
+}
+
+/// [`LeaseBackend`] wrapper that injects faults into operations of the inner backend,
+/// for testing how code using leases handles failures.
+///
+/// Clones share the same faults, so a clone can be kept to inject faults into
+/// a [`Client`](crate::Client) built with another clone.
+///
+/// # Example
+/// ```
+/// # async fn foo(dynamodb_client: aws_sdk_dynamodb::Client) -> anyhow::Result<()> {
+/// use dynamodb_lease::backend::{DynamoDbBackend, FaultyBackend, Operation};
+///
+/// let backend = FaultyBackend::new(DynamoDbBackend::new(dynamodb_client, "test-leases"));
+/// let client = dynamodb_lease::Client::builder()
+///     .build_with_backend(backend.clone())
+///     .await?;
+///
+/// let lease = client.acquire("important-job-123").await?;
+///
+/// // simulate another holder taking over the lease
+/// backend.condition_fail_next(Operation::Extend, 1);
+/// lease.lost().await;
+/// # Ok(()) }
+/// ```
+#[derive(Debug, Clone)]
+pub struct FaultyBackend<B> {
+    inner: B,
+    faults: Arc<Mutex<HashMap<Operation, Faults>>>,
+}
+
+#[derive(Debug, Default)]
+struct Faults {
+    fail: usize,
+    condition_fail: usize,
+    lose_response: usize,
+    latency: Duration,
+}
+
+impl<B: LeaseBackend> FaultyBackend<B> {
+    /// Returns a new wrapper of `inner`, initially with no faults.
+    pub fn new(inner: B) -> Self {
+        Self {
+            inner,
+            faults: <_>::default(),
+        }
+    }
+
+    /// Returns the inner backend.
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    /// Fails the next `n` calls of `op` with [`Error::Backend`], as if the backend
+    /// were unavailable.
+    pub fn fail_next(&self, op: Operation, n: usize) {
+        self.faults.lock().unwrap().entry(op).or_default().fail = n;
+    }
+
+    /// Fails the condition of the next `n` calls of `op`, as if another holder had
+    /// taken over the lease. I.e. acquiring returns [`PutOutcome::Held`] & extending or releasing
+    /// reports the lease is no longer held.
+    ///
+    /// The inner backend is not called, so is unchanged. Has no effect on [`Operation::Describe`]
+    /// or [`Operation::Enqueue`].
+    pub fn condition_fail_next(&self, op: Operation, n: usize) {
+        self.faults
+            .lock()
+            .unwrap()
+            .entry(op)
+            .or_default()
+            .condition_fail = n;
+    }
+
+    /// Fails the next `n` calls of `op` with [`Error::Backend`] *after* calling the inner
+    /// backend, as if the operation was applied but the response was lost.
+    pub fn lose_response_next(&self, op: Operation, n: usize) {
+        self.faults
+            .lock()
+            .unwrap()
+            .entry(op)
+            .or_default()
+            .lose_response = n;
+    }
+
+    /// Adds `latency` before each call of `op`. Set to zero to remove.
+    pub fn set_latency(&self, op: Operation, latency: Duration) {
+        self.faults.lock().unwrap().entry(op).or_default().latency = latency;
+    }
+
+    /// Removes all faults.
+    pub fn clear(&self) {
+        self.faults.lock().unwrap().clear();
+    }
+
+    /// Applies faults for a call of `op`.
+    ///
+    /// Returns `true` if the call should fail its condition.
+    async fn fault(&self, op: Operation) -> Result<bool> {
+        let latency = self
+            .faults
+            .lock()
+            .unwrap()
+            .get(&op)
+            .map(|f| f.latency)
+            .unwrap_or_default();
+        if latency > Duration::ZERO {
+            tokio::time::sleep(latency).await;
+        }
+
+        let mut faults = self.faults.lock().unwrap();
+        let Some(faults) = faults.get_mut(&op) else {
+            return Ok(false);
+        };
+        if faults.fail > 0 {
+            faults.fail -= 1;
+            return Err(Error::Backend(format!("injected {op:?} failure").into()));
+        }
+        if faults.condition_fail > 0 {
+            faults.condition_fail -= 1;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    /// Applies faults to the `result` of a call of `op` to the inner backend.
+    fn response<T>(&self, op: Operation, result: Result<T>) -> Result<T> {
+        let mut faults = self.faults.lock().unwrap();
+        match faults.get_mut(&op) {
+            Some(faults) if faults.lose_response > 0 => {
+                faults.lose_response -= 1;
+                Err(Error::Backend(
+                    format!("injected {op:?} lost response").into(),
+                ))
+            }
+            _ => result,
+        }
+    }
+}
+
+#[async_trait]
+impl<B: LeaseBackend> LeaseBackend for FaultyBackend<B> {
+    async fn put_lease(&self, lease: PutLease) -> Result<PutOutcome> {
+        if self.fault(Operation::Acquire).await? {
+            return Ok(PutOutcome::Held { expires_in: None });
+        }
+        let result = self.inner.put_lease(lease).await;
+        self.response(Operation::Acquire, result)
+    }
+
+    async fn extend_lease(
+        &self,
+        key: &str,
+        lease_v: Uuid,
+        new_lease_v: Uuid,
+        ttl: Duration,
+    ) -> Result<bool> {
+        if self.fault(Operation::Extend).await? {
+            return Ok(false);
+        }
+        let result = self
+            .inner
+            .extend_lease(key, lease_v, new_lease_v, ttl)
+            .await;
+        self.response(Operation::Extend, result)
+    }
+
+    async fn release_lease(&self, key: &str, lease_v: Uuid) -> Result<bool> {
+        if self.fault(Operation::Release).await? {
+            return Ok(false);
+        }
+        let result = self.inner.release_lease(key, lease_v).await;
+        self.response(Operation::Release, result)
+    }
+
+    async fn put_shared_lease(&self, lease: PutLease) -> Result<PutOutcome> {
+        if self.fault(Operation::Acquire).await? {
+            return Ok(PutOutcome::Held { expires_in: None });
+        }
+        let result = self.inner.put_shared_lease(lease).await;
+        self.response(Operation::Acquire, result)
+    }
+
+    async fn extend_shared_lease(&self, key: &str, reader_id: Uuid, ttl: Duration) -> Result<bool> {
+        if self.fault(Operation::Extend).await? {
+            return Ok(false);
+        }
+        let result = self.inner.extend_shared_lease(key, reader_id, ttl).await;
+        self.response(Operation::Extend, result)
+    }
+
+    async fn release_shared_lease(&self, key: &str, reader_id: Uuid) -> Result<bool> {
+        if self.fault(Operation::Release).await? {
+            return Ok(false);
+        }
+        let result = self.inner.release_shared_lease(key, reader_id).await;
+        self.response(Operation::Release, result)
+    }
+
+    async fn put_leases(&self, leases: Vec<PutLease>) -> Result<Option<Vec<u64>>> {
+        if self.fault(Operation::Acquire).await? {
+            return Ok(None);
+        }
+        let result = self.inner.put_leases(leases).await;
+        self.response(Operation::Acquire, result)
+    }
+
+    async fn extend_leases(
+        &self,
+        keys: &[String],
+        lease_v: Uuid,
+        new_lease_v: Uuid,
+        ttl: Duration,
+    ) -> Result<bool> {
+        if self.fault(Operation::Extend).await? {
+            return Ok(false);
+        }
+        let result = self
+            .inner
+            .extend_leases(keys, lease_v, new_lease_v, ttl)
+            .await;
+        self.response(Operation::Extend, result)
+    }
+
+    async fn release_leases(&self, keys: &[String], lease_v: Uuid) -> Result<bool> {
+        if self.fault(Operation::Release).await? {
+            return Ok(false);
+        }
+        let result = self.inner.release_leases(keys, lease_v).await;
+        self.response(Operation::Release, result)
+    }
+
+    async fn put_hierarchical_lease(
+        &self,
+        lease: PutLease,
+        intents: &Intents,
+    ) -> Result<PutOutcome> {
+        if self.fault(Operation::Acquire).await? {
+            return Ok(PutOutcome::Held { expires_in: None });
+        }
+        let result = self.inner.put_hierarchical_lease(lease, intents).await;
+        self.response(Operation::Acquire, result)
+    }
+
+    async fn extend_hierarchical_lease(
+        &self,
+        key: &str,
+        intents: &Intents,
+        lease_v: Uuid,
+        new_lease_v: Uuid,
+        ttl: Duration,
+    ) -> Result<bool> {
+        if self.fault(Operation::Extend).await? {
+            return Ok(false);
+        }
+        let result = self
+            .inner
+            .extend_hierarchical_lease(key, intents, lease_v, new_lease_v, ttl)
+            .await;
+        self.response(Operation::Extend, result)
+    }
+
+    async fn release_hierarchical_lease(
+        &self,
+        key: &str,
+        intents: &Intents,
+        lease_v: Uuid,
+    ) -> Result<bool> {
+        if self.fault(Operation::Release).await? {
+            return Ok(false);
+        }
+        let result = self
+            .inner
+            .release_hierarchical_lease(key, intents, lease_v)
+            .await;
+        self.response(Operation::Release, result)
+    }
+
+    async fn enqueue(&self, key: &str, ticket: Uuid, ttl: Duration) -> Result<()> {
+        self.inner.enqueue(key, ticket, ttl).await?;
+        self.fault(Operation::Enqueue).await?;
+        self.response(Operation::Enqueue, Ok(()))
+    }
+
+    async fn put_queued_lease(&self, lease: PutLease, ticket: Uuid) -> Result<PutOutcome> {
+        if self.fault(Operation::Acquire).await? {
+            return Ok(PutOutcome::Held { expires_in: None });
+        }
+        let result = self.inner.put_queued_lease(lease, ticket).await;
+        self.response(Operation::Acquire, result)
+    }
+
+    async fn dequeue(&self, key: &str, ticket: Uuid) -> Result<()> {
+        self.inner.dequeue(key, ticket).await
+    }
+
+    async fn wait_for_change(&self, key: &str, max_wait: Duration) {
+        self.inner.wait_for_change(key, max_wait).await
+    }
+
+    async fn get_lease(&self, key: &str) -> Result<Option<LeaseInfo>> {
+        self.fault(Operation::Describe).await?;
+        let result = self.inner.get_lease(key).await;
+        self.response(Operation::Describe, result)
+    }
+
+    fn list_leases(&self, key_prefix: Option<String>) -> LeaseStream {
+        self.inner.list_leases(key_prefix)
+    }
+
+    async fn validate(&self) -> Result<()> {
+        self.inner.validate().await
+    }
+}
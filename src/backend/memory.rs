@@ -0,0 +1,542 @@
+use crate::{
+    backend::{Intents, LeaseBackend, LeaseStream, PutLease, PutOutcome},
+    Error, LeaseInfo, Result,
+};
+use async_trait::async_trait;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use time::OffsetDateTime;
+use tokio::time::Instant;
+use uuid::Uuid;
+
+/// In-memory [`LeaseBackend`] for testing code that uses leases without dynamodb.
+///
+/// Clones share the same leases, so multiple [`Client`](crate::Client)s can be built
+/// from clones to simulate separate processes.
+///
+/// Lease expiry uses [`tokio::time`], so works with paused clocks,
+/// e.g. `#[tokio::test(start_paused = true)]`.
+///
+/// # Example
+/// ```
+/// # async fn foo() -> anyhow::Result<()> {
+/// use dynamodb_lease::backend::MemoryBackend;
+///
+/// let backend = MemoryBackend::default();
+/// let client = dynamodb_lease::Client::builder()
+///     .build_with_backend(backend.clone())
+///     .await?;
+///
+/// // fail the next background extension
+/// backend.fail_next_extends(1);
+/// # Ok(()) }
+/// ```
+#[derive(Debug, Clone)]
+pub struct MemoryBackend {
+    inner: Arc<Mutex<Inner>>,
+    /// Wall clock time at `start`, used to report expiry timestamps.
+    start_utc: OffsetDateTime,
+    start: Instant,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    items: HashMap<String, Item>,
+    fail_extends: usize,
+    fail_releases: usize,
+}
+
+#[derive(Debug, Default)]
+struct Item {
+    lease: Option<Held>,
+    fence: u64,
+    /// Shared holder ids & their expiries.
+    readers: HashMap<Uuid, Instant>,
+    /// Fair queue tickets & their expiries.
+    queue: VecDeque<(Uuid, Instant)>,
+    /// Hierarchical descendant intent ids & their expiries.
+    intents: HashMap<Uuid, Instant>,
+    /// When the item may be removed by ttl.
+    ttl: Option<Instant>,
+}
+
+impl Item {
+    /// Puts a new exclusive lease if not held, returning the new fence token.
+    fn put_lease(&mut self, lease: &PutLease, now: Instant) -> PutOutcome {
+        if self.is_held(lease, now) {
+            return self.held(now);
+        }
+
+        self.fence += 1;
+        self.ttl = Some(now + lease.ttl);
+        self.lease = Some(Held {
+            lease_v: lease.lease_v,
+            expiry: now + lease.ttl,
+            owner_id: lease.owner_id.clone(),
+            metadata: lease.metadata.clone(),
+        });
+        PutOutcome::Acquired { fence: self.fence }
+    }
+
+    /// Raises the ttl to at least `expiry`.
+    fn keep_until(&mut self, expiry: Instant) {
+        self.ttl = Some(self.ttl.map_or(expiry, |ttl| ttl.max(expiry)));
+    }
+
+    /// Returns `true` if an exclusive or shared lease, or descendant intent, prevents
+    /// putting `lease`, removing expired shared holders & intents.
+    fn is_held(&mut self, lease: &PutLease, now: Instant) -> bool {
+        let expired = |expiry: Instant| expiry + lease.clock_skew_margin < now;
+        self.readers.retain(|_, expiry| !expired(*expiry));
+        self.intents.retain(|_, expiry| !expired(*expiry));
+        !self.readers.is_empty() || !self.intents.is_empty() || self.is_held_exclusively(lease, now)
+    }
+
+    /// Returns `true` if an unexpired exclusive lease prevents putting `lease`.
+    fn is_held_exclusively(&self, lease: &PutLease, now: Instant) -> bool {
+        self.lease
+            .as_ref()
+            .is_some_and(|l| l.expiry + lease.clock_skew_margin >= now)
+    }
+
+    /// Returns `true` if the exclusive lease has version `lease_v`.
+    fn is_held_by(&self, lease_v: Uuid) -> bool {
+        self.lease.as_ref().is_some_and(|l| l.lease_v == lease_v)
+    }
+
+    /// Returns [`PutOutcome::Held`] with the time until the last exclusive or shared
+    /// holder expires.
+    fn held(&self, now: Instant) -> PutOutcome {
+        let expiry = self
+            .lease
+            .iter()
+            .map(|l| l.expiry)
+            .chain(self.readers.values().copied())
+            .chain(self.intents.values().copied())
+            .max();
+        PutOutcome::Held {
+            expires_in: expiry.map(|expiry| expiry.saturating_duration_since(now)),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Held {
+    lease_v: Uuid,
+    expiry: Instant,
+    owner_id: String,
+    metadata: HashMap<String, String>,
+}
+
+impl Default for MemoryBackend {
+    fn default() -> Self {
+        Self {
+            inner: <_>::default(),
+            start_utc: OffsetDateTime::now_utc(),
+            start: Instant::now(),
+        }
+    }
+}
+
+impl MemoryBackend {
+    /// Fails the next `n` lease extensions with [`Error::Backend`].
+    pub fn fail_next_extends(&self, n: usize) {
+        self.inner.lock().unwrap().fail_extends = n;
+    }
+
+    /// Fails the next `n` lease releases with [`Error::Backend`].
+    pub fn fail_next_releases(&self, n: usize) {
+        self.inner.lock().unwrap().fail_releases = n;
+    }
+
+    /// Removes items whose ttl, i.e. the dynamodb `lease_expiry`, has passed, as dynamodb
+    /// ttl may at any time after expiry. Fence tokens are kept, as dynamodb fence tokens
+    /// continue from the current time.
+    pub fn remove_ttl_expired(&self) {
+        let now = Instant::now();
+        for item in self.inner.lock().unwrap().items.values_mut() {
+            if item.ttl.is_some_and(|ttl| ttl < now) {
+                *item = Item {
+                    fence: item.fence,
+                    ..<_>::default()
+                };
+            }
+        }
+    }
+
+    fn lease_info(&self, key: &str, item: &Item) -> Option<LeaseInfo> {
+        let held = item.lease.as_ref()?;
+        Some(LeaseInfo {
+            key: key.into(),
+            expiry: self.start_utc + (held.expiry - self.start),
+            expired: held.expiry <= Instant::now(),
+            lease_version: held.lease_v.to_string(),
+            fence_token: item.fence,
+            owner_id: Some(held.owner_id.clone()),
+            metadata: held.metadata.clone(),
+        })
+    }
+}
+
+#[async_trait]
+impl LeaseBackend for MemoryBackend {
+    async fn put_lease(&self, lease: PutLease) -> Result<PutOutcome> {
+        let mut inner = self.inner.lock().unwrap();
+        let item = inner.items.entry(lease.key.clone()).or_default();
+        Ok(item.put_lease(&lease, Instant::now()))
+    }
+
+    async fn extend_lease(
+        &self,
+        key: &str,
+        lease_v: Uuid,
+        new_lease_v: Uuid,
+        ttl: Duration,
+    ) -> Result<bool> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.fail_extends > 0 {
+            inner.fail_extends -= 1;
+            return Err(injected_failure("extend"));
+        }
+
+        let Some(item) = inner.items.get_mut(key) else {
+            return Ok(false);
+        };
+        match &mut item.lease {
+            Some(held) if held.lease_v == lease_v || held.lease_v == new_lease_v => {
+                held.lease_v = new_lease_v;
+                held.expiry = Instant::now() + ttl;
+                item.ttl = Some(held.expiry);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn release_lease(&self, key: &str, lease_v: Uuid) -> Result<bool> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.fail_releases > 0 {
+            inner.fail_releases -= 1;
+            return Err(injected_failure("release"));
+        }
+
+        match inner.items.get_mut(key) {
+            Some(item) if item.is_held_by(lease_v) => {
+                item.lease = None;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn put_shared_lease(&self, lease: PutLease) -> Result<PutOutcome> {
+        let now = Instant::now();
+        let mut inner = self.inner.lock().unwrap();
+        let item = inner.items.entry(lease.key).or_default();
+
+        match &item.lease {
+            Some(held) if held.expiry + lease.clock_skew_margin >= now => return Ok(item.held(now)),
+            _ => item.lease = None,
+        }
+        item.readers.insert(lease.lease_v, now + lease.ttl);
+        item.ttl = Some(now + lease.ttl);
+        Ok(PutOutcome::Acquired { fence: item.fence })
+    }
+
+    async fn extend_shared_lease(&self, key: &str, reader_id: Uuid, ttl: Duration) -> Result<bool> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.fail_extends > 0 {
+            inner.fail_extends -= 1;
+            return Err(injected_failure("extend"));
+        }
+
+        let Some(item) = inner.items.get_mut(key) else {
+            return Ok(false);
+        };
+        match item.readers.get_mut(&reader_id) {
+            Some(expiry) => {
+                *expiry = Instant::now() + ttl;
+                item.ttl = Some(*expiry);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn release_shared_lease(&self, key: &str, reader_id: Uuid) -> Result<bool> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.fail_releases > 0 {
+            inner.fail_releases -= 1;
+            return Err(injected_failure("release"));
+        }
+
+        Ok(inner
+            .items
+            .get_mut(key)
+            .and_then(|item| item.readers.remove(&reader_id))
+            .is_some())
+    }
+
+    async fn put_leases(&self, leases: Vec<PutLease>) -> Result<Option<Vec<u64>>> {
+        let now = Instant::now();
+        let mut inner = self.inner.lock().unwrap();
+        for lease in &leases {
+            let item = inner.items.entry(lease.key.clone()).or_default();
+            if item.is_held(lease, now) {
+                return Ok(None);
+            }
+        }
+
+        let fences = leases
+            .iter()
+            .filter_map(|lease| {
+                let item = inner.items.get_mut(&lease.key)?;
+                match item.put_lease(lease, now) {
+                    PutOutcome::Acquired { fence } => Some(fence),
+                    PutOutcome::Held { .. } => None,
+                }
+            })
+            .collect();
+        Ok(Some(fences))
+    }
+
+    async fn extend_leases(
+        &self,
+        keys: &[String],
+        lease_v: Uuid,
+        new_lease_v: Uuid,
+        ttl: Duration,
+    ) -> Result<bool> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.fail_extends > 0 {
+            inner.fail_extends -= 1;
+            return Err(injected_failure("extend"));
+        }
+
+        let all_held = keys.iter().all(|key| {
+            inner
+                .items
+                .get(key)
+                .is_some_and(|item| item.is_held_by(lease_v) || item.is_held_by(new_lease_v))
+        });
+        if !all_held {
+            return Ok(false);
+        }
+        let expiry = Instant::now() + ttl;
+        for key in keys {
+            if let Some(item) = inner.items.get_mut(key) {
+                if let Some(held) = &mut item.lease {
+                    held.lease_v = new_lease_v;
+                    held.expiry = expiry;
+                    item.ttl = Some(expiry);
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    async fn release_leases(&self, keys: &[String], lease_v: Uuid) -> Result<bool> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.fail_releases > 0 {
+            inner.fail_releases -= 1;
+            return Err(injected_failure("release"));
+        }
+
+        let all_held = keys.iter().all(|key| {
+            inner
+                .items
+                .get(key)
+                .is_some_and(|item| item.is_held_by(lease_v))
+        });
+        if !all_held {
+            return Ok(false);
+        }
+        for key in keys {
+            if let Some(item) = inner.items.get_mut(key) {
+                item.lease = None;
+            }
+        }
+        Ok(true)
+    }
+
+    async fn put_hierarchical_lease(
+        &self,
+        lease: PutLease,
+        intents: &Intents,
+    ) -> Result<PutOutcome> {
+        let now = Instant::now();
+        let mut inner = self.inner.lock().unwrap();
+        for ancestor in &intents.ancestors {
+            let item = inner.items.entry(ancestor.clone()).or_default();
+            if item.is_held_exclusively(&lease, now) {
+                return Ok(item.held(now));
+            }
+        }
+
+        let item = inner.items.entry(lease.key.clone()).or_default();
+        let outcome = item.put_lease(&lease, now);
+        if let PutOutcome::Acquired { .. } = outcome {
+            for ancestor in &intents.ancestors {
+                let item = inner.items.entry(ancestor.clone()).or_default();
+                // also remove any expired exclusive lease
+                item.lease = None;
+                item.intents.insert(intents.id, now + lease.ttl);
+                // other intents may expire later
+                item.keep_until(now + lease.ttl);
+            }
+        }
+        Ok(outcome)
+    }
+
+    async fn extend_hierarchical_lease(
+        &self,
+        key: &str,
+        intents: &Intents,
+        lease_v: Uuid,
+        new_lease_v: Uuid,
+        ttl: Duration,
+    ) -> Result<bool> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.fail_extends > 0 {
+            inner.fail_extends -= 1;
+            return Err(injected_failure("extend"));
+        }
+
+        let lease_held = inner
+            .items
+            .get(key)
+            .is_some_and(|item| item.is_held_by(lease_v) || item.is_held_by(new_lease_v));
+        let intents_held = intents.ancestors.iter().all(|ancestor| {
+            inner
+                .items
+                .get(ancestor)
+                .is_some_and(|item| item.intents.contains_key(&intents.id))
+        });
+        if !lease_held || !intents_held {
+            return Ok(false);
+        }
+        let expiry = Instant::now() + ttl;
+        if let Some(item) = inner.items.get_mut(key) {
+            if let Some(held) = &mut item.lease {
+                held.lease_v = new_lease_v;
+                held.expiry = expiry;
+                item.ttl = Some(expiry);
+            }
+        }
+        for ancestor in &intents.ancestors {
+            if let Some(item) = inner.items.get_mut(ancestor) {
+                item.intents.insert(intents.id, expiry);
+                item.keep_until(expiry);
+            }
+        }
+        Ok(true)
+    }
+
+    async fn release_hierarchical_lease(
+        &self,
+        key: &str,
+        intents: &Intents,
+        lease_v: Uuid,
+    ) -> Result<bool> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.fail_releases > 0 {
+            inner.fail_releases -= 1;
+            return Err(injected_failure("release"));
+        }
+
+        let mut released = match inner.items.get_mut(key) {
+            Some(item) if item.is_held_by(lease_v) => {
+                item.lease = None;
+                true
+            }
+            _ => false,
+        };
+        for ancestor in &intents.ancestors {
+            released &= inner
+                .items
+                .get_mut(ancestor)
+                .and_then(|item| item.intents.remove(&intents.id))
+                .is_some();
+        }
+        Ok(released)
+    }
+
+    async fn enqueue(&self, key: &str, ticket: Uuid, ttl: Duration) -> Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        let item = inner.items.entry(key.into()).or_default();
+        let expiry = Instant::now() + ttl;
+        item.queue.push_back((ticket, expiry));
+        // as with dynamodb the item is kept at least until the ticket expires
+        item.keep_until(expiry);
+        Ok(())
+    }
+
+    async fn put_queued_lease(&self, lease: PutLease, ticket: Uuid) -> Result<PutOutcome> {
+        let now = Instant::now();
+        let mut inner = self.inner.lock().unwrap();
+        let item = inner.items.entry(lease.key.clone()).or_default();
+
+        let Some(position) = item.queue.iter().position(|(t, _)| *t == ticket) else {
+            item.queue.push_back((ticket, now + lease.ttl));
+            item.keep_until(now + lease.ttl);
+            return Ok(item.held(now));
+        };
+
+        // remove expired tickets ahead
+        let mut rest = item.queue.split_off(position);
+        item.queue
+            .retain(|(_, expiry)| *expiry + lease.clock_skew_margin >= now);
+        let position = item.queue.len();
+        item.queue.append(&mut rest);
+
+        if position == 0 {
+            if let acquired @ PutOutcome::Acquired { .. } = item.put_lease(&lease, now) {
+                item.queue.pop_front();
+                return Ok(acquired);
+            }
+        }
+        item.queue[position].1 = now + lease.ttl;
+        item.keep_until(now + lease.ttl);
+        Ok(item.held(now))
+    }
+
+    async fn dequeue(&self, key: &str, ticket: Uuid) -> Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(item) = inner.items.get_mut(key) {
+            item.queue.retain(|(t, _)| *t != ticket);
+        }
+        Ok(())
+    }
+
+    async fn get_lease(&self, key: &str) -> Result<Option<LeaseInfo>> {
+        let inner = self.inner.lock().unwrap();
+        Ok(inner
+            .items
+            .get(key)
+            .and_then(|item| self.lease_info(key, item)))
+    }
+
+    fn list_leases(&self, key_prefix: Option<String>) -> LeaseStream {
+        let inner = self.inner.lock().unwrap();
+        let prefix = key_prefix.as_deref().unwrap_or_default();
+        let leases: Vec<_> = inner
+            .items
+            .iter()
+            .filter(|(key, _)| key.starts_with(prefix))
+            .filter_map(|(key, item)| self.lease_info(key, item))
+            .map(Ok)
+            .collect();
+        Box::pin(tokio_stream::iter(leases))
+    }
+
+    async fn validate(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+fn injected_failure(op: &str) -> Error {
+    Error::Backend(format!("injected {op} failure").into())
+}
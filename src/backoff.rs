@@ -0,0 +1,99 @@
+use std::time::Duration;
+
+/// Strategy for waiting between attempts to acquire a lease that is held elsewhere,
+/// see [`ClientBuilder::acquire_backoff`](crate::ClientBuilder::acquire_backoff).
+///
+/// Waits are also capped to when the holder's lease could be acquired if not extended,
+/// i.e. its expiry plus the clock skew margin, when the backend reports it. So with a
+/// high max, waiters for a lease held for a long while sleep until about its expiry.
+///
+/// # Example
+/// ```
+/// # use std::time::Duration;
+/// # use dynamodb_lease::AcquireBackoff;
+/// let backoff = AcquireBackoff::DecorrelatedJitter {
+///     base: Duration::from_millis(100),
+///     max: Duration::from_secs(10),
+/// };
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AcquireBackoff {
+    /// Waits the same duration between each attempt.
+    Fixed(Duration),
+    /// Waits `initial` after the first attempt, doubling after each subsequent
+    /// attempt up to `max`. Jitter is applied so actual waits will be between
+    /// half & all of this value.
+    Exponential {
+        /// Wait after the first attempt.
+        initial: Duration,
+        /// Max wait between attempts.
+        max: Duration,
+    },
+    /// "Decorrelated jitter", each wait is random between `base` & 3x the previous
+    /// wait, up to `max`. Spreads out waiters that started together.
+    DecorrelatedJitter {
+        /// Min wait between attempts.
+        base: Duration,
+        /// Max wait between attempts.
+        max: Duration,
+    },
+}
+
+impl Default for AcquireBackoff {
+    /// Fixed `1s`.
+    fn default() -> Self {
+        Self::Fixed(Duration::from_secs(1))
+    }
+}
+
+impl AcquireBackoff {
+    /// Starts backing off a new sequence of attempts.
+    pub(crate) fn start(self) -> Backoff {
+        Backoff {
+            strategy: self,
+            attempt: 0,
+            prev: Duration::ZERO,
+        }
+    }
+}
+
+/// Backoff state of a sequence of attempts, see [`AcquireBackoff`].
+#[derive(Debug)]
+pub(crate) struct Backoff {
+    strategy: AcquireBackoff,
+    attempt: u32,
+    prev: Duration,
+}
+
+impl Backoff {
+    /// Returns the wait after a failed attempt.
+    ///
+    /// `acquirable_in` is the time until the held lease could be acquired if not extended,
+    /// if known, which caps the wait.
+    pub(crate) fn next(&mut self, acquirable_in: Option<Duration>) -> Duration {
+        self.attempt = self.attempt.saturating_add(1);
+        let wait = match self.strategy {
+            AcquireBackoff::Fixed(wait) => wait,
+            AcquireBackoff::Exponential { initial, max } => {
+                let wait = initial
+                    .saturating_mul(2_u32.saturating_pow(self.attempt - 1))
+                    .min(max);
+                // "equal jitter", half fixed & half random
+                wait / 2 + (wait / 2).mul_f64(fastrand::f64())
+            }
+            AcquireBackoff::DecorrelatedJitter { base, max } => {
+                let upper = self.prev.max(base).saturating_mul(3);
+                let wait = (base + (upper - base).mul_f64(fastrand::f64())).min(max);
+                self.prev = wait;
+                wait
+            }
+        };
+
+        match acquirable_in {
+            // zero if the lease was acquirable, e.g. another client raced to acquire it
+            Some(acquirable_in) if acquirable_in > Duration::ZERO => wait.min(acquirable_in),
+            _ => wait,
+        }
+    }
+}
@@ -1,21 +1,42 @@
-use crate::Client;
-use std::time::Duration;
+use crate::{
+    backend::{DynamoDbBackend, LeaseBackend, TableConfig, TableLayout},
+    AcquireBackoff, Client, Error, Result, RetryPolicy,
+};
+use std::{sync::Arc, time::Duration};
 
 /// [`Client`] builder.
 pub struct ClientBuilder {
     table_name: String,
+    table_layout: TableLayout,
     lease_ttl_seconds: u32,
+    clock_skew_margin_seconds: u32,
     extend_period: Option<Duration>,
-    acquire_cooldown: Duration,
+    acquire_backoff: AcquireBackoff,
+    fair: bool,
+    hierarchical: bool,
+    retry_policy: RetryPolicy,
+    owner_id: Option<String>,
+    key_prefix: String,
+    #[cfg(feature = "streams")]
+    streams_client: Option<aws_sdk_dynamodbstreams::Client>,
 }
 
 impl Default for ClientBuilder {
     fn default() -> Self {
         Self {
             table_name: "leases".into(),
+            table_layout: <_>::default(),
             lease_ttl_seconds: 60,
+            clock_skew_margin_seconds: 5,
             extend_period: None,
-            acquire_cooldown: Duration::from_secs(1),
+            acquire_backoff: <_>::default(),
+            fair: false,
+            hierarchical: false,
+            retry_policy: <_>::default(),
+            owner_id: None,
+            key_prefix: String::new(),
+            #[cfg(feature = "streams")]
+            streams_client: None,
         }
     }
 }
@@ -30,6 +51,33 @@ impl ClientBuilder {
         self
     }
 
+    /// Sets the lease table key schema & attribute names, e.g. to store leases in an
+    /// existing single-table design table.
+    ///
+    /// # Example
+    /// ```
+    /// # async fn foo(dynamodb_client: aws_sdk_dynamodb::Client) -> anyhow::Result<()> {
+    /// use dynamodb_lease::backend::{SortKey, TableLayout};
+    ///
+    /// let client = dynamodb_lease::Client::builder()
+    ///     .table_name("app")
+    ///     .table_layout(
+    ///         TableLayout::default()
+    ///             .key_attribute("pk")
+    ///             .sort_key("sk", SortKey::Fixed("lease".into()))
+    ///             .lease_expiry_attribute("ttl"),
+    ///     )
+    ///     .build_and_check_db(dynamodb_client)
+    ///     .await?;
+    /// # Ok(()) }
+    /// ```
+    ///
+    /// Default [`TableLayout::default`].
+    pub fn table_layout(mut self, layout: TableLayout) -> Self {
+        self.table_layout = layout;
+        self
+    }
+
     /// Sets the time to live for each lease (or lease extension) in seconds.
     /// **Must be at least 2**.
     ///
@@ -45,7 +93,7 @@ impl ClientBuilder {
     ///
     /// So, for example, if the locked task can take 1s to 5m a ttl of 10m should provide
     /// a decent guarantee that such tasks will never execute concurrently. In normal operation
-    /// each lease will release (be deleted) immediately after dropping, so having a high
+    /// each lease will release immediately after dropping, so having a high
     /// ttl only affects the edge case where the extend/drop db interactions fail.
     ///
     /// Default `60`.
@@ -61,6 +109,21 @@ impl ClientBuilder {
         self
     }
 
+    /// Sets the margin in seconds after a lease's expiry before it may be acquired
+    /// by another client, if not released or extended by the holder.
+    ///
+    /// As expiry timestamps are generated using the holder's clock this margin mitigates
+    /// clock skew between clients.
+    ///
+    /// Note: Expired leases are also eventually removed by dynamodb ttl, however this
+    /// can take a long time.
+    ///
+    /// Default `5`.
+    pub fn clock_skew_margin_seconds(mut self, seconds: u32) -> Self {
+        self.clock_skew_margin_seconds = seconds;
+        self
+    }
+
     /// Sets the periodic duration between each background attempt to extend the lease. These
     /// happen continually while the [`crate::Lease`] is alive.
     ///
@@ -79,9 +142,118 @@ impl ClientBuilder {
 
     /// Sets how long [`Client::acquire`] waits between attempts to acquire a lease.
     ///
+    /// Shorthand for [`AcquireBackoff::Fixed`], see [`ClientBuilder::acquire_backoff`].
+    ///
     /// Default `1s`.
-    pub fn acquire_cooldown(mut self, cooldown: Duration) -> Self {
-        self.acquire_cooldown = cooldown;
+    pub fn acquire_cooldown(self, cooldown: Duration) -> Self {
+        self.acquire_backoff(AcquireBackoff::Fixed(cooldown))
+    }
+
+    /// Sets the [`AcquireBackoff`] strategy [`Client::acquire`] uses to wait between
+    /// attempts to acquire a lease.
+    ///
+    /// Many waiters retrying at the same fixed cooldown may contend in bursts, exponential
+    /// or decorrelated jitter backoff spreads attempts out.
+    ///
+    /// Default [`AcquireBackoff::Fixed`] `1s`.
+    ///
+    /// # Panics
+    /// Panics if an exponential `initial` or decorrelated jitter `base` wait is zero,
+    /// or greater than `max`.
+    pub fn acquire_backoff(mut self, backoff: AcquireBackoff) -> Self {
+        match backoff {
+            AcquireBackoff::Fixed(_) => {}
+            AcquireBackoff::Exponential { initial: min, max }
+            | AcquireBackoff::DecorrelatedJitter { base: min, max } => {
+                assert!(min > Duration::ZERO, "min wait must be greater than zero");
+                assert!(max >= min, "max wait must be at least the min wait");
+            }
+        }
+        self.acquire_backoff = backoff;
+        self
+    }
+
+    /// Sets whether exclusive leases are acquired fairly, in the order clients started
+    /// waiting for them.
+    ///
+    /// In fair mode each attempt to acquire joins a queue of tickets stored with the lease
+    /// & the lease is only acquired when first in the queue. Waiting clients keep their
+    /// tickets alive with each attempt, abandoned tickets expire
+    /// after the lease ttl.
+    ///
+    /// Note: Fairness is only guaranteed if all clients acquiring a key use fair mode.
+    /// Shared leases are not queued. Fair mode requires more db requests for each attempt.
+    /// Not supported in [`ClientBuilder::hierarchical`] mode.
+    ///
+    /// Default `false`.
+    pub fn fair(mut self, fair: bool) -> Self {
+        self.fair = fair;
+        self
+    }
+
+    /// Sets whether exclusive leases are hierarchical, so a lease for a `/` separated path
+    /// like `"tenant/42"` conflicts with leases of its descendants, e.g. `"tenant/42/invoice/7"`,
+    /// & ancestors, e.g. `"tenant"`.
+    ///
+    /// In hierarchical mode acquiring a lease also adds an intent marker to each ancestor
+    /// key, in the same transaction, if no ancestor is held. A key with intent markers cannot
+    /// be acquired until they are removed, when the descendant leases are released, or expire.
+    /// So conflicts are detected without scanning the table.
+    ///
+    /// Note: All clients acquiring keys of a hierarchy should use hierarchical mode &
+    /// the same [`ClientBuilder::lease_ttl_seconds`]. Shared leases, permits,
+    /// [`crate::Client::try_acquire_many`] & fair mode are not supported in hierarchical mode
+    /// & return [`Error::Unsupported`]. Acquiring & extending a lease writes every ancestor key in a transaction,
+    /// so deep paths are more expensive. Paths of more than 100 keys, including the key itself, exceed the
+    /// dynamodb transaction limit & return [`Error::Unsupported`].
+    ///
+    /// Default `false`.
+    pub fn hierarchical(mut self, hierarchical: bool) -> Self {
+        self.hierarchical = hierarchical;
+        self
+    }
+
+    /// Sets the [`RetryPolicy`] used by the background tasks that extend a [`crate::Lease`]
+    /// and release it on drop.
+    ///
+    /// Default [`RetryPolicy::default`].
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Sets the owner id stored with each lease acquired by the client, in the `owner` field.
+    /// Useful to identify which process holds a lease.
+    ///
+    /// Default `"{hostname}:{pid}"`.
+    pub fn owner_id(mut self, owner_id: impl Into<String>) -> Self {
+        self.owner_id = Some(owner_id.into());
+        self
+    }
+
+    /// Sets a prefix added to every lease key, e.g. `"svc-a/"`, so multiple services can
+    /// share a lease table without key collisions.
+    ///
+    /// The prefix is removed from keys returned by [`crate::Client::describe`] &
+    /// [`crate::Client::list_leases`]. See also [`crate::Client::namespace`].
+    ///
+    /// Default `""`, no prefix.
+    pub fn key_prefix(mut self, key_prefix: impl Into<String>) -> Self {
+        self.key_prefix = key_prefix.into();
+        self
+    }
+
+    /// Sets a dynamodb streams client used to wait for leases to be released, instead of
+    /// polling according to [`ClientBuilder::acquire_backoff`]. The lease table must have a
+    /// stream enabled, otherwise polling is used.
+    ///
+    /// The backoff still applies as the max wait between attempts, so can be increased
+    /// to reduce db usage.
+    ///
+    /// See [`DynamoDbBackend::with_streams`].
+    #[cfg(feature = "streams")]
+    pub fn streams_client(mut self, streams_client: aws_sdk_dynamodbstreams::Client) -> Self {
+        self.streams_client = Some(streams_client);
         self
     }
 
@@ -92,7 +264,51 @@ impl ClientBuilder {
     pub async fn build_and_check_db(
         self,
         dynamodb_client: aws_sdk_dynamodb::Client,
-    ) -> anyhow::Result<Client> {
+    ) -> Result<Client> {
+        let backend = self.dynamodb_backend(dynamodb_client);
+        self.build_with_backend(backend).await
+    }
+
+    /// Builds a [`Client`], first creating the dynamodb table if it does not exist
+    /// using `table_config`, then checks the table has the correct schema.
+    ///
+    /// See [`DynamoDbBackend::create_table_if_missing`].
+    ///
+    /// # Example
+    /// ```
+    /// # async fn foo(dynamodb_client: aws_sdk_dynamodb::Client) -> anyhow::Result<()> {
+    /// use dynamodb_lease::backend::TableConfig;
+    ///
+    /// let client = dynamodb_lease::Client::builder()
+    ///     .table_name("example-leases")
+    ///     .build_and_ensure_table(dynamodb_client, TableConfig::default())
+    ///     .await?;
+    /// # Ok(()) }
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `extend_period` is not less than `lease_ttl_seconds`.
+    pub async fn build_and_ensure_table(
+        self,
+        dynamodb_client: aws_sdk_dynamodb::Client,
+        table_config: TableConfig,
+    ) -> Result<Client> {
+        let backend = self.dynamodb_backend(dynamodb_client);
+        backend.create_table_if_missing(&table_config).await?;
+        self.build_with_backend(backend).await
+    }
+
+    /// Builds a [`Client`] using a custom [`LeaseBackend`] and checks it with
+    /// [`LeaseBackend::validate`].
+    ///
+    /// The `table_name` is not used, the backend is responsible for its own configuration.
+    ///
+    /// # Panics
+    /// Panics if `extend_period` is not less than `lease_ttl_seconds`.
+    pub async fn build_with_backend(self, backend: impl LeaseBackend) -> Result<Client> {
+        if self.fair && self.hierarchical {
+            return Err(Error::Unsupported("fair mode in hierarchical mode"));
+        }
         let extend_period = self
             .extend_period
             .unwrap_or_else(|| Duration::from_secs_f64(self.lease_ttl_seconds as f64 / 2.0));
@@ -102,16 +318,42 @@ impl ClientBuilder {
         );
 
         let client = Client {
-            table_name: self.table_name.into(),
-            client: dynamodb_client,
+            backend: Arc::new(backend),
             lease_ttl_seconds: self.lease_ttl_seconds,
+            clock_skew_margin_seconds: self.clock_skew_margin_seconds,
             extend_period,
-            acquire_cooldown: self.acquire_cooldown,
+            acquire_backoff: self.acquire_backoff,
+            fair: self.fair,
+            hierarchical: self.hierarchical,
+            retry_policy: self.retry_policy,
+            owner_id: Arc::new(self.owner_id.unwrap_or_else(default_owner_id)),
+            key_prefix: Arc::new(self.key_prefix),
+            metadata: <_>::default(),
             local_locks: <_>::default(),
         };
 
-        client.check_schema().await?;
+        client.backend.validate().await?;
 
         Ok(client)
     }
+
+    fn dynamodb_backend(&self, dynamodb_client: aws_sdk_dynamodb::Client) -> DynamoDbBackend {
+        #[allow(unused_mut)]
+        let mut backend = DynamoDbBackend::new(dynamodb_client, self.table_name.clone())
+            .with_layout(self.table_layout.clone());
+        #[cfg(feature = "streams")]
+        if let Some(streams_client) = self.streams_client.clone() {
+            backend = backend.with_streams(streams_client);
+        }
+        backend
+    }
+}
+
+/// Returns `"{hostname}:{pid}"`.
+fn default_owner_id() -> String {
+    format!(
+        "{}:{}",
+        gethostname::gethostname().to_string_lossy(),
+        std::process::id()
+    )
 }
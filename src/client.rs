@@ -1,41 +1,43 @@
-use crate::{local::LocalLocks, ClientBuilder, Lease};
-use anyhow::{bail, ensure, Context};
-use aws_sdk_dynamodb::{
-    error::SdkError,
-    operation::{
-        delete_item::{DeleteItemError, DeleteItemOutput},
-        put_item::PutItemError,
-        update_item::UpdateItemError,
-    },
-    types::{AttributeValue, KeyType, ScalarAttributeType},
+use crate::{
+    backend::{Intents, LeaseBackend, PutLease, PutOutcome},
+    held::Target,
+    local::LocalLocks,
+    AcquireBackoff, ClientBuilder, Error, Lease, LeaseInfo, MultiLease, ReleaseOutcome, Result,
+    RetryPolicy, WithLeaseError,
 };
-use aws_smithy_runtime_api::client::orchestrator;
-use std::{
-    cmp::min,
-    sync::Arc,
-    time::{Duration, Instant},
-};
-use time::OffsetDateTime;
+use std::{collections::HashMap, future::Future, pin::Pin, sync::Arc, task::Poll, time::Duration};
+use tokio::time::Instant;
+use tokio_stream::{Stream, StreamExt};
+use tokio_util::sync::CancellationToken;
 use tracing::instrument;
 use uuid::Uuid;
 
-const KEY_FIELD: &str = "key";
-const LEASE_EXPIRY_FIELD: &str = "lease_expiry";
-const LEASE_VERSION_FIELD: &str = "lease_version";
+/// Max keys of [`Client::try_acquire_many`], the dynamodb transaction item limit.
+const MAX_MANY_KEYS: usize = 100;
+
+/// Separator of hierarchical key paths, see [`ClientBuilder::hierarchical`].
+const PATH_SEPARATOR: char = '/';
 
 /// Client for acquiring [`Lease`]s.
 ///
-/// Communicates with dynamodb to acquire, extend and delete distributed leases.
+/// Communicates with dynamodb, or another [`LeaseBackend`], to acquire, extend and
+/// release distributed leases.
 ///
 /// Local mutex locks are also used to eliminate db contention for usage within
 /// a single `Client` instance or clone.
 #[derive(Debug, Clone)]
 pub struct Client {
-    pub(crate) client: aws_sdk_dynamodb::Client,
-    pub(crate) table_name: Arc<String>,
+    pub(crate) backend: Arc<dyn LeaseBackend>,
     pub(crate) lease_ttl_seconds: u32,
+    pub(crate) clock_skew_margin_seconds: u32,
     pub(crate) extend_period: Duration,
-    pub(crate) acquire_cooldown: Duration,
+    pub(crate) acquire_backoff: AcquireBackoff,
+    pub(crate) fair: bool,
+    pub(crate) hierarchical: bool,
+    pub(crate) retry_policy: RetryPolicy,
+    pub(crate) owner_id: Arc<String>,
+    pub(crate) key_prefix: Arc<String>,
+    pub(crate) metadata: Arc<HashMap<String, String>>,
     pub(crate) local_locks: LocalLocks,
 }
 
@@ -45,39 +47,281 @@ impl Client {
         <_>::default()
     }
 
+    /// Returns a clone of this client that stores the given `metadata` with each lease
+    /// it acquires, replacing any metadata of this client.
+    ///
+    /// Metadata is readable from the [`Lease`] & by inspecting the lease table.
+    /// To store metadata with a single lease see [`Client::acquire_with_metadata`].
+    ///
+    /// # Example
+    /// ```
+    /// # async fn foo(client: dynamodb_lease::Client) -> anyhow::Result<()> {
+    /// let lease = client
+    ///     .with_metadata([("job".into(), "reindex".into())].into())
+    ///     .acquire("important-job-123")
+    ///     .await?;
+    /// # Ok(()) }
+    /// ```
+    pub fn with_metadata(&self, metadata: HashMap<String, String>) -> Client {
+        Self {
+            metadata: Arc::new(metadata),
+            ..self.clone()
+        }
+    }
+
+    /// Returns a clone of this client scoped to the `namespace`, prefixing all keys
+    /// after this client's [`Client::key_prefix`].
+    ///
+    /// Keys returned by [`Client::describe`] & [`Client::list_leases`] have the prefix
+    /// removed, so the namespaced client only deals in its own keys.
+    ///
+    /// # Example
+    /// ```
+    /// # async fn foo(client: dynamodb_lease::Client) -> anyhow::Result<()> {
+    /// let billing = client.namespace("billing/");
+    /// // acquires the lease for "billing/job-1", not conflicting with other namespaces
+    /// let lease = billing.acquire("job-1").await?;
+    /// # Ok(()) }
+    /// ```
+    pub fn namespace(&self, namespace: impl AsRef<str>) -> Client {
+        Self {
+            key_prefix: Arc::new(format!("{}{}", self.key_prefix, namespace.as_ref())),
+            ..self.clone()
+        }
+    }
+
+    /// Returns the prefix added to every key by this client, see [`ClientBuilder::key_prefix`].
+    pub fn key_prefix(&self) -> &str {
+        &self.key_prefix
+    }
+
+    /// Returns the owner id stored with each lease acquired by this client.
+    pub fn owner_id(&self) -> &str {
+        &self.owner_id
+    }
+
     /// Trys to acquire a new [`Lease`] for the given `key`.
     ///
     /// If this lease has already been acquired elsewhere `Ok(None)` is returned.
     ///
     /// Does not wait to acquire a lease, to do so see [`Client::acquire`].
     #[instrument(skip_all)]
-    pub async fn try_acquire(&self, key: impl Into<String>) -> anyhow::Result<Option<Lease>> {
-        let key = key.into();
-        let local_guard = match self.local_locks.try_lock(key.clone()) {
+    pub async fn try_acquire(&self, key: impl Into<String>) -> Result<Option<Lease>> {
+        Ok(self.try_put_lease(self.prefixed(key)).await?.ok())
+    }
+
+    /// Trys to acquire a new [`Lease`] for the given `key`, as [`Client::try_acquire`],
+    /// storing the given `metadata` with this lease instead of this client's metadata.
+    pub async fn try_acquire_with_metadata(
+        &self,
+        key: impl Into<String>,
+        metadata: HashMap<String, String>,
+    ) -> Result<Option<Lease>> {
+        self.with_metadata(metadata).try_acquire(key).await
+    }
+
+    /// Acquires a new [`Lease`] for the given `key`, as [`Client::acquire`], storing the
+    /// given `metadata` with this lease instead of this client's metadata.
+    ///
+    /// # Example
+    /// ```
+    /// # async fn foo(client: dynamodb_lease::Client) -> anyhow::Result<()> {
+    /// let lease = client
+    ///     .acquire_with_metadata("important-job-123", [("job".into(), "reindex".into())].into())
+    ///     .await?;
+    /// assert_eq!(lease.metadata()["job"], "reindex");
+    /// # Ok(()) }
+    /// ```
+    pub async fn acquire_with_metadata(
+        &self,
+        key: impl Into<String>,
+        metadata: HashMap<String, String>,
+    ) -> Result<Lease> {
+        self.with_metadata(metadata).acquire(key).await
+    }
+
+    /// Acquires a new [`Lease`] for the given `key`. May wait until successful if the lease
+    /// has already been acquired elsewhere.
+    ///
+    /// Attempts are retried according to [`ClientBuilder::acquire_backoff`], or sooner if the
+    /// backend observes a change, see [`ClientBuilder::streams_client`].
+    ///
+    /// To try to acquire without waiting see [`Client::try_acquire`].
+    #[instrument(skip_all)]
+    pub async fn acquire(&self, key: impl Into<String>) -> Result<Lease> {
+        let key = self.prefixed(key);
+        let local_guard = self.local_locks.lock(key.clone()).await;
+        let mut ticket = self.enqueue(&key).await?;
+        let mut backoff = self.acquire_backoff.start();
+
+        loop {
+            let acquirable_in = match self.put_lease(key.clone(), ticket.as_mut()).await? {
+                Ok(lease) => return Ok(lease.with_local_guard(local_guard)),
+                Err(acquirable_in) => acquirable_in,
+            };
+            self.backend
+                .wait_for_change(&key, backoff.next(acquirable_in))
+                .await;
+        }
+    }
+
+    /// Acquires a new exclusive [`Lease`] for the given `key`, same as [`Client::acquire`].
+    ///
+    /// Waits until shared leases for the `key` have been released or expired. New shared leases
+    /// are still acquired while waiting, see [`Client::acquire_shared`].
+    pub async fn acquire_exclusive(&self, key: impl Into<String>) -> Result<Lease> {
+        self.acquire(key).await
+    }
+
+    /// Trys to acquire a new shared [`Lease`] for the given `key`.
+    ///
+    /// Any number of shared leases can be held concurrently for a key, but not while
+    /// an exclusive lease is held. If an exclusive lease is held `Ok(None)` is returned.
+    ///
+    /// Note: All clients acquiring shared leases for a key should use the same
+    /// [`ClientBuilder::lease_ttl_seconds`]. Not supported in [`ClientBuilder::hierarchical`] mode.
+    #[instrument(skip_all)]
+    pub async fn try_acquire_shared(&self, key: impl Into<String>) -> Result<Option<Lease>> {
+        let key = self.prefixed(key);
+        let local_guard = match self.local_locks.try_lock_shared(key.clone()) {
             Ok(g) => g,
             Err(_) => return Ok(None),
         };
 
-        match self.put_lease(key).await {
-            Ok(Some(lease)) => Ok(Some(lease.with_local_guard(local_guard))),
-            x => x,
+        Ok(self
+            .put_shared_lease(key)
+            .await?
+            .ok()
+            .map(|lease| lease.with_local_guard(local_guard)))
+    }
+
+    /// Acquires a new shared [`Lease`] for the given `key`. May wait until successful if
+    /// an exclusive lease has already been acquired elsewhere.
+    ///
+    /// Any number of shared leases can be held concurrently for a key, but not while
+    /// an exclusive lease is held, see [`Client::acquire_exclusive`].
+    ///
+    /// Shared leases are not blocked by waiting exclusive acquirers, so while overlapping
+    /// shared leases are continually held exclusive acquirers may wait indefinitely.
+    ///
+    /// Note: All clients acquiring shared leases for a key should use the same
+    /// [`ClientBuilder::lease_ttl_seconds`]. Not supported in [`ClientBuilder::hierarchical`] mode.
+    #[instrument(skip_all)]
+    pub async fn acquire_shared(&self, key: impl Into<String>) -> Result<Lease> {
+        let key = self.prefixed(key);
+        let local_guard = self.local_locks.lock_shared(key.clone()).await;
+        let mut backoff = self.acquire_backoff.start();
+
+        loop {
+            let acquirable_in = match self.put_shared_lease(key.clone()).await? {
+                Ok(lease) => return Ok(lease.with_local_guard(local_guard)),
+                Err(acquirable_in) => acquirable_in,
+            };
+            self.backend
+                .wait_for_change(&key, backoff.next(acquirable_in))
+                .await;
         }
     }
 
-    /// Acquires a new [`Lease`] for the given `key`. May wait until successful if the lease
-    /// has already been acquired elsewhere.
+    /// Trys to acquire one of `max_permits` permits for the given `key`, allowing up to
+    /// `max_permits` concurrent holders of the key.
     ///
-    /// To try to acquire without waiting see [`Client::try_acquire`].
+    /// Each permit is an exclusive [`Lease`] of a slot key `"{key}#{n}"` for `n` in
+    /// `0..max_permits`. If all permits are held `Ok(None)` is returned.
+    ///
+    /// Note: All clients acquiring permits for a key should use the same `max_permits`.
+    /// Not supported in [`ClientBuilder::hierarchical`] mode.
+    ///
+    /// # Panics
+    /// If `max_permits` is zero.
     #[instrument(skip_all)]
-    pub async fn acquire(&self, key: impl Into<String>) -> anyhow::Result<Lease> {
+    pub async fn try_acquire_permit(
+        &self,
+        key: impl Into<String>,
+        max_permits: u32,
+    ) -> Result<Option<Lease>> {
+        assert!(max_permits > 0, "max_permits must be greater than zero");
+        self.check_not_hierarchical("permits in hierarchical mode")?;
         let key = key.into();
-        let local_guard = self.local_locks.lock(key.clone()).await;
 
+        Ok(self.put_permit(&key, max_permits).await?.ok())
+    }
+
+    /// Acquires one of `max_permits` permits for the given `key`, allowing up to
+    /// `max_permits` concurrent holders of the key. May wait until successful if all
+    /// permits have already been acquired.
+    ///
+    /// Each permit is an exclusive [`Lease`] of a slot key `"{key}#{n}"` for `n` in
+    /// `0..max_permits`, extended in the background & released on drop. Attempts are retried
+    /// as [`Client::acquire`], or sooner if the backend observes a change to any slot.
+    ///
+    /// Note: All clients acquiring permits for a key should use the same `max_permits`.
+    /// Not supported in [`ClientBuilder::hierarchical`] mode.
+    ///
+    /// # Panics
+    /// If `max_permits` is zero.
+    #[instrument(skip_all)]
+    pub async fn acquire_permit(&self, key: impl Into<String>, max_permits: u32) -> Result<Lease> {
+        assert!(max_permits > 0, "max_permits must be greater than zero");
+        self.check_not_hierarchical("permits in hierarchical mode")?;
+        let key = key.into();
+        let slots: Vec<_> = (0..max_permits)
+            .map(|slot| self.prefixed(format!("{key}#{slot}")))
+            .collect();
+        let mut backoff = self.acquire_backoff.start();
         loop {
-            if let Some(lease) = self.put_lease(key.clone()).await? {
-                return Ok(lease.with_local_guard(local_guard));
-            }
-            tokio::time::sleep(self.acquire_cooldown).await;
+            let acquirable_in = match self.put_permit(&key, max_permits).await? {
+                Ok(lease) => return Ok(lease),
+                Err(acquirable_in) => acquirable_in,
+            };
+            // wait for a change to any slot
+            let max_wait = backoff.next(acquirable_in);
+            let mut waits: Vec<_> = slots
+                .iter()
+                .map(|slot| self.backend.wait_for_change(slot, max_wait))
+                .collect();
+            std::future::poll_fn(|cx| {
+                match waits
+                    .iter_mut()
+                    .any(|wait| wait.as_mut().poll(cx).is_ready())
+                {
+                    true => Poll::Ready(()),
+                    false => Poll::Pending,
+                }
+            })
+            .await;
+        }
+    }
+
+    /// Acquires a new [`Lease`] for the given `key`, same as [`Client::acquire`], unless
+    /// `cancel` is cancelled first in which case [`Error::Cancelled`] is returned.
+    ///
+    /// Useful to stop waiting cleanly, e.g. on shutdown.
+    ///
+    /// # Example
+    /// ```
+    /// # async fn foo(client: dynamodb_lease::Client) -> anyhow::Result<()> {
+    /// use tokio_util::sync::CancellationToken;
+    ///
+    /// let shutdown = CancellationToken::new();
+    /// # shutdown.cancel();
+    /// match client.acquire_until("important-job-123", shutdown.clone()).await {
+    ///     Ok(lease) => println!("acquired"),
+    ///     Err(dynamodb_lease::Error::Cancelled) => println!("shutting down"),
+    ///     Err(err) => return Err(err.into()),
+    /// }
+    /// # Ok(()) }
+    /// ```
+    #[instrument(skip_all)]
+    pub async fn acquire_until(
+        &self,
+        key: impl Into<String>,
+        cancel: CancellationToken,
+    ) -> Result<Lease> {
+        tokio::select! {
+            biased;
+            _ = cancel.cancelled() => Err(Error::Cancelled),
+            lease = self.acquire(key) => lease,
         }
     }
 
@@ -90,191 +334,527 @@ impl Client {
         &self,
         key: impl Into<String>,
         max_wait: Duration,
-    ) -> anyhow::Result<Lease> {
+    ) -> Result<Lease> {
         let start = Instant::now();
-        let key = key.into();
+        let key = self.prefixed(key);
 
         let local_guard = tokio::time::timeout(max_wait, self.local_locks.lock(key.clone()))
             .await
-            .context("Could not acquire within {max_wait:?}")?;
+            .map_err(|_| Error::Timeout(max_wait))?;
+        let mut ticket = self.enqueue(&key).await?;
+        let mut backoff = self.acquire_backoff.start();
 
         loop {
-            if let Some(lease) = self.put_lease(key.clone()).await? {
-                return Ok(lease.with_local_guard(local_guard));
-            }
+            let acquirable_in = match self.put_lease(key.clone(), ticket.as_mut()).await? {
+                Ok(lease) => return Ok(lease.with_local_guard(local_guard)),
+                Err(acquirable_in) => acquirable_in,
+            };
             let elapsed = start.elapsed();
             if elapsed > max_wait {
-                bail!("Could not acquire within {max_wait:?}");
+                return Err(Error::Timeout(max_wait));
             }
             let remaining_max_wait = max_wait - elapsed;
-            tokio::time::sleep(min(self.acquire_cooldown, remaining_max_wait)).await;
+            let wait = backoff.next(acquirable_in).min(remaining_max_wait);
+            self.backend.wait_for_change(&key, wait).await;
         }
     }
 
-    /// Put a new lease into the db.
-    async fn put_lease(&self, key: String) -> anyhow::Result<Option<Lease>> {
-        let expiry_timestamp =
-            OffsetDateTime::now_utc().unix_timestamp() + i64::from(self.lease_ttl_seconds);
-        let lease_v = Uuid::new_v4();
-
-        let put = self
-            .client
-            .put_item()
-            .table_name(self.table_name.as_str())
-            .item(KEY_FIELD, AttributeValue::S(key.clone()))
-            .item(
-                LEASE_EXPIRY_FIELD,
-                AttributeValue::N(expiry_timestamp.to_string()),
-            )
-            .item(LEASE_VERSION_FIELD, AttributeValue::S(lease_v.to_string()))
-            .condition_expression(format!("attribute_not_exists({LEASE_VERSION_FIELD})"))
-            .send()
-            .await;
+    /// Trys to acquire exclusive leases for all the given `keys` together, e.g. to lock
+    /// both accounts of a transfer.
+    ///
+    /// Acquisition is all-or-nothing, using a single dynamodb transaction. If any lease has
+    /// already been acquired elsewhere `Ok(None)` is returned & none are acquired. Unlike
+    /// acquiring leases one at a time this cannot deadlock with other holders.
+    ///
+    /// The leases are extended & released together, see [`MultiLease`]. Duplicate keys are
+    /// ignored. Fair mode, [`ClientBuilder::fair`], does not apply. Not supported in
+    /// [`ClientBuilder::hierarchical`] mode.
+    ///
+    /// # Example
+    /// ```
+    /// # async fn foo(client: dynamodb_lease::Client) -> anyhow::Result<()> {
+    /// if let Some(leases) = client.try_acquire_many(["account-a", "account-b"]).await? {
+    ///     // transfer between the accounts
+    ///     leases.release().await?;
+    /// }
+    /// # Ok(()) }
+    /// ```
+    ///
+    /// # Panics
+    /// If `keys` is empty or has more than 100 distinct keys, the dynamodb transaction limit.
+    #[instrument(skip_all)]
+    pub async fn try_acquire_many<K: Into<String>>(
+        &self,
+        keys: impl IntoIterator<Item = K>,
+    ) -> Result<Option<MultiLease>> {
+        let mut keys: Vec<_> = keys.into_iter().map(|key| self.prefixed(key)).collect();
+        keys.sort();
+        keys.dedup();
+        assert!(!keys.is_empty(), "keys must not be empty");
+        assert!(
+            keys.len() <= MAX_MANY_KEYS,
+            "keys must have at most {MAX_MANY_KEYS} distinct keys"
+        );
+        self.check_not_hierarchical("multi-key leases in hierarchical mode")?;
 
-        match put {
-            Err(SdkError::ServiceError(se))
-                if matches!(se.err(), PutItemError::ConditionalCheckFailedException(..)) =>
-            {
+        // lock in sorted key order, so concurrent local acquisitions cannot deadlock
+        let mut local_guards = Vec::with_capacity(keys.len());
+        for key in &keys {
+            match self.local_locks.try_lock(key.clone()) {
+                Ok(guard) => local_guards.push(guard),
+                Err(_) => {
+                    drop(local_guards);
+                    self.try_clean_local_locks(&keys);
+                    return Ok(None);
+                }
+            }
+        }
+
+        let client = self.clone();
+        let put_keys = keys.clone();
+        let lease = cancel_safe(async move {
+            let expiry = Instant::now() + client.lease_ttl();
+            let lease_v = Uuid::new_v4();
+            let puts = put_keys
+                .iter()
+                .map(|key| client.put_request(key, lease_v))
+                .collect();
+            let fences = match client.backend.put_leases(puts).await {
+                Ok(fences) => fences,
+                // nothing was put
+                Err(err @ Error::Unsupported(_)) => return Err(err),
+                Err(err) => {
+                    // the leases may have been put, e.g. if the response was lost
+                    if let Err(err) = client.backend.release_leases(&put_keys, lease_v).await {
+                        tracing::warn!("Failed to release leases after failed acquire: {err}");
+                    }
+                    return Err(err);
+                }
+            };
+            Ok(fences
+                .map(|fences| MultiLease::new(client.clone(), put_keys, lease_v, expiry, fences)))
+        })
+        .await?;
+
+        match lease {
+            Some(lease) => Ok(Some(lease.with_local_guards(local_guards))),
+            None => {
+                drop(local_guards);
+                self.try_clean_local_locks(&keys);
                 Ok(None)
             }
-            Err(err) => Err(err.into()),
-            Ok(_) => Ok(Some(Lease::new(self.clone(), key, lease_v))),
         }
     }
 
-    /// Delete a lease with a given `key` & `lease_v`.
+    /// Acquires a [`Lease`] for the given `key`, runs the future returned by `f` while
+    /// holding it & then releases it.
+    ///
+    /// The future is boxed so it may borrow the lease, e.g. to read the fence token.
+    /// See [`Client::with_lease_owned`] for futures that do not borrow the lease.
+    ///
+    /// If the lease is lost, i.e. could not be extended, the future is dropped & this
+    /// returns [`WithLeaseError::LeaseLost`]. The lease is explicitly released, waiting
+    /// for the outcome, before returning.
+    ///
+    /// # Example
+    /// ```
+    /// # async fn foo(client: dynamodb_lease::Client) -> anyhow::Result<()> {
+    /// # async fn reindex(fence: u64) {}
+    /// client
+    ///     .with_lease("important-job-123", |lease| {
+    ///         Box::pin(async move { reindex(lease.fence_token()).await })
+    ///     })
+    ///     .await?;
+    /// # Ok(()) }
+    /// ```
     #[instrument(skip_all)]
-    pub(crate) async fn delete_lease(
-        &self,
-        key: String,
-        lease_v: Uuid,
-    ) -> Result<DeleteItemOutput, SdkError<DeleteItemError, orchestrator::HttpResponse>> {
-        self.client
-            .delete_item()
-            .table_name(self.table_name.as_str())
-            .key(KEY_FIELD, AttributeValue::S(key))
-            .condition_expression(format!("{LEASE_VERSION_FIELD}=:lease_v"))
-            .expression_attribute_values(":lease_v", AttributeValue::S(lease_v.to_string()))
-            .send()
-            .await
+    pub async fn with_lease<F, T>(&self, key: impl Into<String>, f: F) -> Result<T, WithLeaseError>
+    where
+        F: for<'a> FnOnce(&'a Lease) -> Pin<Box<dyn Future<Output = T> + Send + 'a>>,
+    {
+        let lease = self.acquire(key).await.map_err(WithLeaseError::Acquire)?;
+        let out = until_lost(&lease, f(&lease)).await;
+        release_after(lease, out).await
     }
 
-    /// Cleanup local lock memory for the given `key` if not in use.
-    pub(crate) fn try_clean_local_lock(&self, key: String) {
-        self.local_locks.try_remove(key)
+    /// Acquires a [`Lease`] for the given `key`, runs the future returned by `f` while
+    /// holding it & then releases it, same as [`Client::with_lease`].
+    ///
+    /// The future is not boxed & need not be `Send`, but cannot borrow the lease.
+    /// `f` can read the lease, e.g. the fence token, before returning the future.
+    ///
+    /// # Example
+    /// ```
+    /// # async fn foo(client: dynamodb_lease::Client) -> anyhow::Result<()> {
+    /// # async fn reindex(fence: u64) {}
+    /// client
+    ///     .with_lease_owned("important-job-123", |lease| reindex(lease.fence_token()))
+    ///     .await?;
+    /// # Ok(()) }
+    /// ```
+    #[instrument(skip_all)]
+    pub async fn with_lease_owned<F, Fut, T>(
+        &self,
+        key: impl Into<String>,
+        f: F,
+    ) -> Result<T, WithLeaseError>
+    where
+        F: FnOnce(&Lease) -> Fut,
+        Fut: Future<Output = T>,
+    {
+        let lease = self.acquire(key).await.map_err(WithLeaseError::Acquire)?;
+        let out = until_lost(&lease, f(&lease)).await;
+        release_after(lease, out).await
     }
 
-    /// Extends an active lease. Returns the new `lease_v` uuid.
+    /// Returns info about the current holder of the lease for `key`, without acquiring it.
+    ///
+    /// Returns `Ok(None)` if the lease is not held.
+    ///
+    /// Note: The lease may have expired without being released, see [`LeaseInfo::expired`].
+    /// Only exclusive leases are described, shared holders are ignored.
     #[instrument(skip_all)]
-    pub(crate) async fn extend_lease(
+    pub async fn describe(&self, key: impl Into<String>) -> Result<Option<LeaseInfo>> {
+        let info = self.backend.get_lease(&self.prefixed(key)).await?;
+        Ok(info.map(|info| self.unprefixed(info)))
+    }
+
+    /// Returns a stream of all held leases, using paginated _Scan_ requests.
+    ///
+    /// Note: Leases that have expired without being released, e.g. if the holder crashed,
+    /// are included with [`LeaseInfo::expired`] set. Only exclusive leases are listed,
+    /// keys held only by shared holders are not.
+    pub fn list_leases(&self) -> impl Stream<Item = Result<LeaseInfo>> + Send + 'static {
+        self.list_leases_with_prefix("")
+    }
+
+    /// Returns a stream of all held leases with keys starting with `key_prefix`,
+    /// using paginated _Scan_ requests.
+    ///
+    /// Note: Leases that have expired without being released, e.g. if the holder crashed,
+    /// are included with [`LeaseInfo::expired`] set. Only exclusive leases are listed,
+    /// keys held only by shared holders are not.
+    pub fn list_leases_with_prefix(
+        &self,
+        key_prefix: impl Into<String>,
+    ) -> impl Stream<Item = Result<LeaseInfo>> + Send + 'static {
+        let key_prefix = self.prefixed(key_prefix);
+        let client = self.clone();
+        self.backend
+            .list_leases((!key_prefix.is_empty()).then_some(key_prefix))
+            .map(move |info| info.map(|info| client.unprefixed(info)))
+    }
+
+    /// Returns `key` with this client's [`Client::key_prefix`].
+    fn prefixed(&self, key: impl Into<String>) -> String {
+        let key = key.into();
+        match self.key_prefix.is_empty() {
+            true => key,
+            false => format!("{}{key}", self.key_prefix),
+        }
+    }
+
+    /// Removes this client's [`Client::key_prefix`] from the lease `info` key.
+    fn unprefixed(&self, mut info: LeaseInfo) -> LeaseInfo {
+        if let Some(key) = info.key.strip_prefix(self.key_prefix.as_str()) {
+            info.key = key.into();
+        }
+        info
+    }
+
+    /// Joins the fair queue for `key`, if fair mode is enabled.
+    ///
+    /// Cancel safe, if cancelled while the enqueue is in flight the ticket is dropped,
+    /// & so dequeued, once written.
+    async fn enqueue(&self, key: &str) -> Result<Option<QueueTicket>> {
+        if !self.fair {
+            return Ok(None);
+        }
+        let client = self.clone();
+        let key = key.to_owned();
+        cancel_safe(async move {
+            let id = Uuid::new_v4();
+            client.backend.enqueue(&key, id, client.lease_ttl()).await?;
+            Ok(Some(QueueTicket {
+                client,
+                key,
+                id,
+                used: false,
+            }))
+        })
+        .await
+    }
+
+    /// Trys to put a new exclusive lease for the prefixed `key`, with a local lock &
+    /// a fair queue ticket if in fair mode.
+    ///
+    /// Returns `Ok(Err(_))` if the lease is held, with the time until it could be acquired
+    /// if not extended, if known.
+    async fn try_put_lease(&self, key: String) -> Result<Result<Lease, Option<Duration>>> {
+        let local_guard = match self.local_locks.try_lock(key.clone()) {
+            Ok(g) => g,
+            Err(_) => return Ok(Err(None)),
+        };
+        let mut ticket = self.enqueue(&key).await?;
+
+        Ok(self
+            .put_lease(key, ticket.as_mut())
+            .await?
+            .map(|lease| lease.with_local_guard(local_guard)))
+    }
+
+    /// Trys to put a permit lease for each slot of `key`, see [`Client::try_acquire_permit`].
+    ///
+    /// Returns `Ok(Err(_))` if all permits are held, with the time until the first could be
+    /// acquired if not extended, if known.
+    async fn put_permit(
+        &self,
+        key: &str,
+        max_permits: u32,
+    ) -> Result<Result<Lease, Option<Duration>>> {
+        let mut acquirable_in: Option<Duration> = None;
+        // start at a random slot to reduce contention between clients
+        let offset = fastrand::u32(0..max_permits);
+        for n in 0..max_permits {
+            let slot = (offset + n) % max_permits;
+            match self
+                .try_put_lease(self.prefixed(format!("{key}#{slot}")))
+                .await?
+            {
+                Ok(lease) => return Ok(Ok(lease)),
+                Err(slot_in) => {
+                    acquirable_in = acquirable_in.into_iter().chain(slot_in).min();
+                }
+            }
+        }
+        Ok(Err(acquirable_in))
+    }
+
+    /// Put a new exclusive lease, incrementing the key's fence token.
+    ///
+    /// Succeeds if the lease is not held or has expired (including the clock skew margin).
+    /// If a `ticket` is given it must also be at the front of the fair queue. In hierarchical
+    /// mode no ancestor or descendant key may be held either.
+    ///
+    /// Returns `Ok(Err(_))` if the lease is held, with the time until it could be acquired
+    /// if not extended, if known.
+    async fn put_lease(
         &self,
         key: String,
-        lease_v: Uuid,
-    ) -> Result<Uuid, SdkError<UpdateItemError, orchestrator::HttpResponse>> {
-        let expiry_timestamp =
-            OffsetDateTime::now_utc().unix_timestamp() + i64::from(self.lease_ttl_seconds);
-        let new_lease_v = Uuid::new_v4();
-
-        self.client
-            .update_item()
-            .table_name(self.table_name.as_str())
-            .key(KEY_FIELD, AttributeValue::S(key))
-            .update_expression(format!(
-                "SET {LEASE_VERSION_FIELD}=:new_lease_v, {LEASE_EXPIRY_FIELD}=:expiry"
-            ))
-            .condition_expression(format!("{LEASE_VERSION_FIELD}=:lease_v"))
-            .expression_attribute_values(":new_lease_v", AttributeValue::S(new_lease_v.to_string()))
-            .expression_attribute_values(":lease_v", AttributeValue::S(lease_v.to_string()))
-            .expression_attribute_values(":expiry", AttributeValue::N(expiry_timestamp.to_string()))
-            .send()
-            .await?;
-
-        Ok(new_lease_v)
-    }
-
-    /// Checks table is active & has a valid schema.
-    pub(crate) async fn check_schema(&self) -> anyhow::Result<()> {
-        // fetch table & ttl descriptions concurrently
-        let (table_desc, ttl_desc) = tokio::join!(
-            self.client
-                .describe_table()
-                .table_name(self.table_name.as_str())
-                .send(),
-            self.client
-                .describe_time_to_live()
-                .table_name(self.table_name.as_str())
-                .send()
-        );
+        ticket: Option<&mut QueueTicket>,
+    ) -> Result<Result<Lease, Option<Duration>>> {
+        let client = self.clone();
+        let ticket_id = ticket.as_ref().map(|ticket| ticket.id);
+        let put = cancel_safe(async move {
+            let expiry = Instant::now() + client.lease_ttl();
+            let lease_v = Uuid::new_v4();
+            let put = client.put_request(&key, lease_v);
+            let intents = client.intents(&key, lease_v)?;
+            let outcome = match (&intents, ticket_id) {
+                (Some(intents), _) => {
+                    match client.backend.put_hierarchical_lease(put, intents).await {
+                        Ok(outcome) => outcome,
+                        // nothing was put
+                        Err(err @ Error::Unsupported(_)) => return Err(err),
+                        Err(err) => {
+                            // the lease & markers may have been put, e.g. if the response was lost
+                            let release = client
+                                .backend
+                                .release_hierarchical_lease(&key, intents, lease_v);
+                            if let Err(err) = release.await {
+                                tracing::warn!(
+                                    "Failed to release lease after failed acquire: {err}"
+                                );
+                            }
+                            return Err(err);
+                        }
+                    }
+                }
+                (None, Some(ticket_id)) => client.backend.put_queued_lease(put, ticket_id).await?,
+                (None, None) => client.backend.put_lease(put).await?,
+            };
+            // without ancestors the lease is extended & released as usual
+            let target = match intents.filter(|intents| !intents.ancestors.is_empty()) {
+                Some(intents) => Target::Hierarchical(key, intents),
+                None => Target::Exclusive(key),
+            };
+            Ok(client
+                .fence_or_acquirable_in(outcome)
+                .map(|fence| Lease::new(client.clone(), target, lease_v, expiry, fence)))
+        })
+        .await?;
 
-        let desc = table_desc
-            .with_context(|| format!("Missing table `{}`?", self.table_name))?
-            .table
-            .context("no table description")?;
-
-        // check "key" field is a S hash key
-        let attrs = desc.attribute_definitions.unwrap_or_default();
-        let key_schema = desc.key_schema.unwrap_or_default();
-        ensure!(
-            key_schema.len() == 1,
-            "Unexpected number of keys ({}) in key_schema, expected 1. Got {:?}",
-            key_schema.len(),
-            vec(key_schema.iter().map(|k| k.attribute_name().unwrap_or("?"))),
-        );
-        let described_kind = attrs
-            .iter()
-            .find(|attr| attr.attribute_name() == Some(KEY_FIELD))
-            .with_context(|| {
-                format!(
-                    "Missing attribute definition for {KEY_FIELD}, available {:?}",
-                    vec(attrs.iter().filter_map(|a| a.attribute_name()))
-                )
-            })?
-            .attribute_type()
-            .with_context(|| format!("Missing attribute type for {KEY_FIELD}"))?;
-        ensure!(
-            described_kind == &ScalarAttributeType::S,
-            "Unexpected attribute type `{:?}` for {}, expected `{:?}`",
-            described_kind,
-            KEY_FIELD,
-            ScalarAttributeType::S,
-        );
+        if let Some(ticket) = ticket {
+            ticket.used = put.is_ok();
+        }
+        Ok(put)
+    }
 
-        let described_key_type = key_schema
-            .iter()
-            .find(|k| k.attribute_name() == Some(KEY_FIELD))
-            .with_context(|| {
-                format!(
-                    "Missing key schema for {KEY_FIELD}, available {:?}",
-                    vec(key_schema.iter().filter_map(|k| k.attribute_name()))
+    /// Put a new shared lease, adding a new shared holder.
+    ///
+    /// Succeeds if the lease is not held exclusively or has expired (including the clock skew margin).
+    ///
+    /// Returns `Ok(Err(_))` if the lease is held exclusively, with the time until it could be
+    /// acquired if not extended, if known.
+    async fn put_shared_lease(&self, key: String) -> Result<Result<Lease, Option<Duration>>> {
+        self.check_not_hierarchical("shared leases in hierarchical mode")?;
+        let client = self.clone();
+        cancel_safe(async move {
+            let expiry = Instant::now() + client.lease_ttl();
+            let reader_id = Uuid::new_v4();
+            let outcome = client
+                .backend
+                .put_shared_lease(client.put_request(&key, reader_id))
+                .await?;
+            Ok(client.fence_or_acquirable_in(outcome).map(|fence| {
+                Lease::new(
+                    client.clone(),
+                    Target::Shared(key),
+                    reader_id,
+                    expiry,
+                    fence,
                 )
-            })?
-            .key_type()
-            .with_context(|| format!("Missing key type for {KEY_FIELD}"))?;
-        ensure!(
-            described_key_type == &KeyType::Hash,
-            "Unexpected key type `{:?}` for {}, expected `{:?}`",
-            described_key_type,
-            KEY_FIELD,
-            KeyType::Hash,
-        );
+            }))
+        })
+        .await
+    }
 
-        // check "lease_expiry" is a ttl field
-        let update_time_to_live_desc = ttl_desc
-            .with_context(|| format!("Missing time_to_live for table `{}`?", self.table_name))?
-            .time_to_live_description
-            .context("no time to live description")?;
+    /// Returns the fence token if acquired, otherwise the time until the held lease could
+    /// be acquired if not extended, i.e. after its expiry & the clock skew margin.
+    fn fence_or_acquirable_in(&self, outcome: PutOutcome) -> Result<u64, Option<Duration>> {
+        match outcome {
+            PutOutcome::Acquired { fence } => Ok(fence),
+            PutOutcome::Held { expires_in } => {
+                Err(expires_in.map(|expires_in| expires_in + self.clock_skew_margin()))
+            }
+        }
+    }
 
-        ensure!(
-            update_time_to_live_desc.attribute_name() == Some(LEASE_EXPIRY_FIELD),
-            "time to live for {} is not set",
-            LEASE_EXPIRY_FIELD,
-        );
+    /// Returns [`Error::Unsupported`] for `operation` in hierarchical mode, as it would not
+    /// conflict with leases of ancestors & descendants.
+    fn check_not_hierarchical(&self, operation: &'static str) -> Result<()> {
+        match self.hierarchical {
+            true => Err(Error::Unsupported(operation)),
+            false => Ok(()),
+        }
+    }
+
+    /// Returns the intent markers of a lease for `key` with marker `id`, if hierarchical
+    /// mode is enabled.
+    ///
+    /// Returns [`Error::Unsupported`] if the lease & ancestors are more than [`MAX_MANY_KEYS`],
+    /// as they are written in one transaction.
+    fn intents(&self, key: &str, id: Uuid) -> Result<Option<Intents>> {
+        if !self.hierarchical {
+            return Ok(None);
+        }
+        // ancestors of the unprefixed path, e.g. "a" & "a/b" for "a/b/c"
+        let path = key.strip_prefix(self.key_prefix.as_str()).unwrap_or(key);
+        let ancestors = path
+            .match_indices(PATH_SEPARATOR)
+            .filter(|(idx, _)| *idx > 0)
+            .map(|(idx, _)| self.prefixed(&path[..idx]))
+            .collect::<Vec<_>>();
+        if ancestors.len() + 1 > MAX_MANY_KEYS {
+            return Err(Error::Unsupported(
+                "hierarchical paths of more than 100 keys",
+            ));
+        }
+        Ok(Some(Intents { ancestors, id }))
+    }
+
+    fn put_request(&self, key: &str, lease_v: Uuid) -> PutLease {
+        PutLease {
+            key: key.into(),
+            lease_v,
+            ttl: self.lease_ttl(),
+            clock_skew_margin: self.clock_skew_margin(),
+            owner_id: self.owner_id.to_string(),
+            metadata: self.metadata.as_ref().clone(),
+        }
+    }
 
-        Ok(())
+    pub(crate) fn lease_ttl(&self) -> Duration {
+        Duration::from_secs(self.lease_ttl_seconds.into())
+    }
+
+    fn clock_skew_margin(&self) -> Duration {
+        Duration::from_secs(self.clock_skew_margin_seconds.into())
+    }
+
+    /// Cleanup local lock memory for the given `key` if not in use.
+    pub(crate) fn try_clean_local_lock(&self, key: String) {
+        self.local_locks.try_remove(key)
+    }
+
+    pub(crate) fn try_clean_local_locks(&self, keys: &[String]) {
+        for key in keys {
+            self.try_clean_local_lock(key.clone());
+        }
+    }
+}
+
+/// Runs a lease `put` in a separate task, so if cancelled while the put is in flight
+/// any acquired [`Lease`], or fair queue ticket, is dropped, & so released, instead of
+/// being orphaned until expiry.
+async fn cancel_safe<T: Send + 'static>(
+    put: impl Future<Output = Result<T>> + Send + 'static,
+) -> Result<T> {
+    match tokio::spawn(put).await {
+        Ok(out) => out,
+        Err(err) if err.is_panic() => std::panic::resume_unwind(err.into_panic()),
+        Err(_) => Err(Error::Cancelled),
     }
 }
 
-#[inline]
-fn vec<T>(iter: impl Iterator<Item = T>) -> Vec<T> {
-    iter.collect()
+/// Runs `work` until complete, or until the `lease` is lost, returning `None`.
+async fn until_lost<T>(lease: &Lease, work: impl Future<Output = T>) -> Option<T> {
+    tokio::select! {
+        biased;
+        _ = lease.lost() => None,
+        out = work => Some(out),
+    }
+}
+
+/// Releases the `lease` after running work with output `out`, `None` if the lease was lost.
+async fn release_after<T>(lease: Lease, out: Option<T>) -> Result<T, WithLeaseError> {
+    let released = lease.release().await;
+    let Some(out) = out else {
+        if let Err(err) = released {
+            tracing::warn!("Failed to release lost lease: {err}");
+        }
+        return Err(WithLeaseError::LeaseLost);
+    };
+    match released.map_err(WithLeaseError::Release)? {
+        ReleaseOutcome::Released => Ok(out),
+        // lost after the work completed, before the loss was observed
+        ReleaseOutcome::AlreadyLost => Err(WithLeaseError::LeaseLost),
+    }
+}
+
+/// A ticket in a key's fair queue, see [`ClientBuilder::fair`].
+///
+/// On drop, if not used to acquire a lease, asynchronously leaves the queue.
+struct QueueTicket {
+    client: Client,
+    key: String,
+    id: Uuid,
+    /// Set once used to acquire a lease, which removes the ticket.
+    used: bool,
+}
+
+impl Drop for QueueTicket {
+    fn drop(&mut self) {
+        if !self.used {
+            let client = self.client.clone();
+            let key = std::mem::take(&mut self.key);
+            let id = self.id;
+            tokio::spawn(async move {
+                if let Err(err) = client.backend.dequeue(&key, id).await {
+                    // the ticket will expire instead
+                    tracing::warn!(key, "Failed to leave fair queue: {err}");
+                }
+            });
+        }
+    }
 }
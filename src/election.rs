@@ -0,0 +1,184 @@
+use crate::{Client, Result};
+use std::time::Duration;
+use tokio::{
+    sync::{mpsc, oneshot, watch},
+    time::Instant,
+};
+
+/// Leader election for a key, continually campaigning to hold the lease for the key
+/// in a background tokio task.
+///
+/// Leadership is lost if the lease cannot be extended, in which case campaigning
+/// resumes. On drop campaigning stops & any held lease is released.
+///
+/// # Example
+/// ```
+/// # async fn foo(client: dynamodb_lease::Client) -> anyhow::Result<()> {
+/// use dynamodb_lease::LeaderElection;
+///
+/// let election = LeaderElection::new(client, "scheduler-leader");
+///
+/// let mut leader = election.leader_receiver();
+/// loop {
+///     match *leader.borrow_and_update() {
+///         true => println!("became leader"),
+///         false => println!("not leader"),
+///     }
+///     leader.changed().await?;
+/// }
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct LeaderElection {
+    client: Client,
+    key: String,
+    is_leader: watch::Receiver<bool>,
+    /// Dropping stops the campaign task.
+    commands: mpsc::UnboundedSender<StepDown>,
+}
+
+#[derive(Debug)]
+struct StepDown {
+    pause: Duration,
+    done: oneshot::Sender<Result<()>>,
+}
+
+impl LeaderElection {
+    /// Starts campaigning for leadership of `key` using `client`.
+    pub fn new(client: Client, key: impl Into<String>) -> Self {
+        let key = key.into();
+        let (is_leader_tx, is_leader) = watch::channel(false);
+        let (commands, command_rx) = mpsc::unbounded_channel();
+
+        tokio::spawn(campaign(
+            client.clone(),
+            key.clone(),
+            is_leader_tx,
+            command_rx,
+        ));
+
+        Self {
+            client,
+            key,
+            is_leader,
+            commands,
+        }
+    }
+
+    /// Returns the election key.
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Returns `true` if currently the leader, i.e. holding the lease for the key.
+    pub fn is_leader(&self) -> bool {
+        *self.is_leader.borrow()
+    }
+
+    /// Returns a [`watch::Receiver`] of leadership, `true` while leader.
+    pub fn leader_receiver(&self) -> watch::Receiver<bool> {
+        self.is_leader.clone()
+    }
+
+    /// Returns the owner id of the current leader, or `None` if there is no leader.
+    ///
+    /// See [`ClientBuilder::owner_id`](crate::ClientBuilder::owner_id).
+    pub async fn leader_owner_id(&self) -> Result<Option<String>> {
+        Ok(self
+            .client
+            .describe(self.key.as_str())
+            .await?
+            .filter(|info| !info.expired)
+            .and_then(|info| info.owner_id))
+    }
+
+    /// Releases leadership, if leader, & pauses campaigning for `pause` so other
+    /// candidates may become leader.
+    pub async fn step_down(&self, pause: Duration) -> Result<()> {
+        let (done, done_rx) = oneshot::channel();
+        if self.commands.send(StepDown { pause, done }).is_err() {
+            // campaign task has stopped, e.g. runtime shutting down
+            return Ok(());
+        }
+        done_rx.await.unwrap_or(Ok(()))
+    }
+}
+
+async fn campaign(
+    client: Client,
+    key: String,
+    is_leader: watch::Sender<bool>,
+    mut commands: mpsc::UnboundedReceiver<StepDown>,
+) {
+    let mut backoff = client.acquire_backoff.start();
+    loop {
+        let lease = tokio::select! {
+            lease = client.acquire(key.as_str()) => lease,
+            command = commands.recv() => match command {
+                Some(StepDown { pause, done }) => {
+                    let _ = done.send(Ok(()));
+                    match paused(pause, &mut commands).await {
+                        true => continue,
+                        false => return,
+                    }
+                }
+                // election dropped
+                None => return,
+            },
+        };
+        let lease = match lease {
+            Ok(lease) => lease,
+            Err(err) => {
+                tracing::warn!(key, "Leader election failed to acquire lease: {err}");
+                match paused(backoff.next(None), &mut commands).await {
+                    true => continue,
+                    false => return,
+                }
+            }
+        };
+        backoff = client.acquire_backoff.start();
+
+        let _ = is_leader.send(true);
+        tokio::select! {
+            _ = lease.lost() => {
+                tracing::warn!(key, "Leadership lost");
+                let _ = is_leader.send(false);
+            }
+            command = commands.recv() => {
+                let _ = is_leader.send(false);
+                match command {
+                    Some(StepDown { pause, done }) => {
+                        let _ = done.send(lease.release().await.map(|_| ()));
+                        if !paused(pause, &mut commands).await {
+                            return;
+                        }
+                    }
+                    // election dropped, lease releases on drop
+                    None => return,
+                }
+            }
+        }
+    }
+}
+
+/// Pauses campaigning for `pause`, while handling commands. Further step downs are
+/// acknowledged & extend the pause if longer.
+///
+/// Returns `false` if the election was dropped.
+async fn paused(pause: Duration, commands: &mut mpsc::UnboundedReceiver<StepDown>) -> bool {
+    let sleep = tokio::time::sleep(pause);
+    tokio::pin!(sleep);
+    loop {
+        tokio::select! {
+            _ = &mut sleep => return true,
+            command = commands.recv() => match command {
+                Some(StepDown { pause, done }) => {
+                    let _ = done.send(Ok(()));
+                    let deadline = sleep.deadline().max(Instant::now() + pause);
+                    sleep.as_mut().reset(deadline);
+                }
+                None => return false,
+            },
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use aws_sdk_dynamodb::error::{ProvideErrorMetadata, SdkError};
+use std::time::Duration;
+
+/// Error codes returned by dynamodb when requests are throttled.
+const THROTTLING_CODES: &[&str] = &[
+    "ProvisionedThroughputExceededException",
+    "RequestLimitExceeded",
+    "ThrottlingException",
+];
+
+/// [`Client`](crate::Client) & [`Lease`](crate::Lease) error.
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    /// Could not acquire a lease within the max wait.
+    #[error("Could not acquire within {0:?}")]
+    Timeout(Duration),
+    /// The lease table does not exist.
+    #[error("Missing table `{table_name}`?")]
+    MissingTable {
+        table_name: String,
+        #[source]
+        source: Box<aws_sdk_dynamodb::Error>,
+    },
+    /// The lease table does not have the expected schema.
+    #[error("Invalid schema for table `{table_name}`: {reason}")]
+    SchemaMismatch { table_name: String, reason: String },
+    /// The lease table did not become active within the wait, see
+    /// [`ClientBuilder::build_and_ensure_table`](crate::ClientBuilder::build_and_ensure_table).
+    #[error("Table not active within {0:?}")]
+    TableNotActive(Duration),
+    /// A dynamodb request was throttled.
+    #[error("dynamodb request throttled")]
+    Throttled(#[source] Box<aws_sdk_dynamodb::Error>),
+    /// A dynamodb request failed.
+    #[error("dynamodb request failed")]
+    Sdk(#[source] Box<aws_sdk_dynamodb::Error>),
+    /// A dynamodb response was missing expected data.
+    #[error("Unexpected dynamodb response: {0}")]
+    UnexpectedResponse(String),
+    /// The operation is not supported, e.g. by the [`LeaseBackend`](crate::backend::LeaseBackend).
+    #[error("{0} not supported")]
+    Unsupported(&'static str),
+    /// A custom [`LeaseBackend`](crate::backend::LeaseBackend) request failed.
+    #[error("Lease backend request failed")]
+    Backend(#[source] Box<dyn std::error::Error + Send + Sync>),
+    /// The operation was cancelled, e.g. because the tokio runtime is shutting down
+    /// or by [`Client::acquire_until`](crate::Client::acquire_until).
+    #[error("Cancelled")]
+    Cancelled,
+}
+
+impl<E, R> From<SdkError<E, R>> for Error
+where
+    E: ProvideErrorMetadata,
+    aws_sdk_dynamodb::Error: From<SdkError<E, R>>,
+{
+    fn from(err: SdkError<E, R>) -> Self {
+        let throttled = err
+            .code()
+            .is_some_and(|code| THROTTLING_CODES.contains(&code));
+        match Box::new(aws_sdk_dynamodb::Error::from(err)) {
+            err if throttled => Self::Throttled(err),
+            err => Self::Sdk(err),
+        }
+    }
+}
+
+/// [`Client::with_lease`](crate::Client::with_lease) error.
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum WithLeaseError {
+    /// The lease could not be acquired.
+    #[error("Failed to acquire lease")]
+    Acquire(#[source] Error),
+    /// The lease was lost, so the work was aborted or may have run without exclusivity.
+    #[error("Lease lost")]
+    LeaseLost,
+    /// The work completed but releasing the lease failed.
+    #[error("Failed to release lease")]
+    Release(#[source] Error),
+}
+
+/// [`Error`] result.
+pub type Result<T, E = Error> = std::result::Result<T, E>;
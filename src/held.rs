@@ -0,0 +1,303 @@
+use crate::{backend::Intents, local::LocalGuard, Client, Error, ReleaseOutcome, Result};
+use std::{future::Future, sync::Arc};
+use tokio::{
+    sync::{watch, Mutex},
+    time::Instant,
+};
+use uuid::Uuid;
+
+/// Held leases & a background task to continuously try to extend them until dropped.
+/// Used by [`Lease`](crate::Lease) & [`MultiLease`](crate::MultiLease).
+///
+/// On drop asynchronously releases the leases.
+#[derive(Debug)]
+pub(crate) struct Held {
+    client: Client,
+    target_lease_v: Arc<(Target, Mutex<LeaseVersion>)>,
+    /// Local guards, in key order, to avoid db contention for leases within the same client.
+    local_guards: Vec<LocalGuard>,
+    /// Set to `true` by the background task when the leases could not be extended.
+    lost: watch::Receiver<bool>,
+    /// Set once releasing has started, so it only happens once.
+    released: bool,
+}
+
+/// The leases held, determining how they are extended & released.
+#[derive(Debug)]
+pub(crate) enum Target {
+    /// An exclusive lease.
+    Exclusive(String),
+    /// A shared lease, see [`Client::acquire_shared`]. The lease version is the fixed
+    /// id of the shared holder.
+    Shared(String),
+    /// An exclusive lease & its ancestor intent markers, see
+    /// [`ClientBuilder::hierarchical`](crate::ClientBuilder::hierarchical).
+    Hierarchical(String, Intents),
+    /// Exclusive leases for sorted keys, extended & released together,
+    /// see [`Client::try_acquire_many`].
+    Many(Vec<String>),
+}
+
+/// The current version of held leases.
+#[derive(Debug)]
+struct LeaseVersion {
+    lease_v: Uuid,
+    /// Local estimate of when this version of the leases expires.
+    expiry: Instant,
+    /// Set once released, to stop extending.
+    released: bool,
+    /// New version of a failed extension attempt, which may have been applied without responding.
+    extending: Option<Uuid>,
+}
+
+impl Held {
+    pub(crate) fn new(client: Client, target: Target, lease_v: Uuid, expiry: Instant) -> Self {
+        let (lost_tx, lost) = watch::channel(false);
+        let held = Self {
+            client,
+            target_lease_v: Arc::new((
+                target,
+                Mutex::new(LeaseVersion {
+                    lease_v,
+                    expiry,
+                    released: false,
+                    extending: None,
+                }),
+            )),
+            local_guards: vec![],
+            lost,
+            released: false,
+        };
+
+        start_periodicly_extending(&held, lost_tx);
+
+        held
+    }
+
+    pub(crate) fn add_local_guards(&mut self, guards: impl IntoIterator<Item = LocalGuard>) {
+        self.local_guards.extend(guards);
+    }
+
+    pub(crate) fn client(&self) -> &Client {
+        &self.client
+    }
+
+    pub(crate) fn target(&self) -> &Target {
+        &self.target_lease_v.0
+    }
+
+    pub(crate) fn is_lost(&self) -> bool {
+        *self.lost.borrow()
+    }
+
+    pub(crate) async fn lost(&self) {
+        let mut lost = self.lost.clone();
+        while !*lost.borrow_and_update() {
+            // sender only drops if the background task has stopped without
+            // extending, in which case the lease cannot be relied upon either
+            if lost.changed().await.is_err() {
+                break;
+            }
+        }
+    }
+
+    pub(crate) fn lost_receiver(&self) -> watch::Receiver<bool> {
+        self.lost.clone()
+    }
+
+    /// Releases the leases & waits for the outcome.
+    ///
+    /// Cancelling this future will not cancel the release.
+    pub(crate) async fn release(mut self) -> Result<ReleaseOutcome> {
+        let released = match tokio::spawn(self.start_release()).await {
+            Ok(released) => released?,
+            Err(err) if err.is_panic() => std::panic::resume_unwind(err.into_panic()),
+            Err(_) => return Err(Error::Cancelled),
+        };
+        match released {
+            true => Ok(ReleaseOutcome::Released),
+            false => Ok(ReleaseOutcome::AlreadyLost),
+        }
+    }
+
+    /// Drops the local guards & returns a future that releases the leases in the db.
+    ///
+    /// Resolves `false` if any lease was no longer held.
+    fn start_release(&mut self) -> impl Future<Output = Result<bool>> {
+        self.released = true;
+        let client = self.client.clone();
+        let target_lease_v = self.target_lease_v.clone();
+
+        // Drop local guards *before* releasing leases to avoid unfair local acquire advantage.
+        // Dropping the local guards after releasing would be more efficient however during
+        // contention that efficiency could starve remote attempts to acquire the leases.
+        self.local_guards.clear();
+        client.try_clean_local_locks(target_lease_v.0.keys());
+
+        let lost = self.lost.clone();
+
+        async move {
+            let (target, version) = &*target_lease_v;
+            let mut version = version.lock().await;
+            version.released = true;
+            let (lease_v, expiry, extending) = (version.lease_v, version.expiry, version.extending);
+            let mut retrying = false;
+            client
+                .retry_policy
+                .retry("release", &target.log_key(), expiry, || {
+                    let retry = std::mem::replace(&mut retrying, true);
+                    let (client, lost) = (&client, &lost);
+                    async move {
+                        let mut released = target.release(client, lease_v).await;
+                        if let (Ok(false), Some(extending)) = (&released, extending) {
+                            released = target.release(client, extending).await;
+                        }
+                        match released {
+                            // a previous attempt may have released but failed to respond,
+                            // the leases cannot have been taken over before they expire
+                            Ok(false) if retry && !*lost.borrow() && Instant::now() < expiry => {
+                                Ok(true)
+                            }
+                            released => released,
+                        }
+                    }
+                })
+                .await
+        }
+    }
+}
+
+impl Target {
+    /// Returns the leased keys.
+    pub(crate) fn keys(&self) -> &[String] {
+        match self {
+            Self::Exclusive(key) | Self::Shared(key) | Self::Hierarchical(key, _) => {
+                std::slice::from_ref(key)
+            }
+            Self::Many(keys) => keys,
+        }
+    }
+
+    /// Returns the keys for logging.
+    fn log_key(&self) -> String {
+        self.keys().join(", ")
+    }
+
+    /// Extends the leases at `lease_v` to `new_lease_v`.
+    ///
+    /// Returns `false` if any lease is no longer held.
+    async fn extend(&self, client: &Client, lease_v: Uuid, new_lease_v: Uuid) -> Result<bool> {
+        let (backend, ttl) = (&client.backend, client.lease_ttl());
+        match self {
+            Self::Exclusive(key) => backend.extend_lease(key, lease_v, new_lease_v, ttl).await,
+            Self::Shared(key) => backend.extend_shared_lease(key, lease_v, ttl).await,
+            Self::Hierarchical(key, intents) => {
+                backend
+                    .extend_hierarchical_lease(key, intents, lease_v, new_lease_v, ttl)
+                    .await
+            }
+            Self::Many(keys) => backend.extend_leases(keys, lease_v, new_lease_v, ttl).await,
+        }
+    }
+
+    /// Releases the leases at `lease_v`.
+    ///
+    /// Returns `false` if any lease was no longer held.
+    async fn release(&self, client: &Client, lease_v: Uuid) -> Result<bool> {
+        let backend = &client.backend;
+        match self {
+            Self::Exclusive(key) => backend.release_lease(key, lease_v).await,
+            Self::Shared(key) => backend.release_shared_lease(key, lease_v).await,
+            Self::Hierarchical(key, intents) => {
+                backend
+                    .release_hierarchical_lease(key, intents, lease_v)
+                    .await
+            }
+            Self::Many(keys) => {
+                let released = backend.release_leases(keys, lease_v).await?;
+                if !released {
+                    // some leases were lost, release any still held
+                    for key in keys {
+                        if let Err(err) = backend.release_lease(key, lease_v).await {
+                            tracing::warn!(key, "Failed to release lease: {err}");
+                        }
+                    }
+                }
+                Ok(released)
+            }
+        }
+    }
+}
+
+fn start_periodicly_extending(held: &Held, lost: watch::Sender<bool>) {
+    let target_lease_v = Arc::downgrade(&held.target_lease_v);
+    let client = held.client.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(client.extend_period).await;
+            let Some(target_lease_v) = target_lease_v.upgrade() else {
+                // lease dropped
+                break;
+            };
+            let (target, version) = &*target_lease_v;
+            let (lease_v, expiry) = match version.lock().await {
+                version if version.released => break,
+                version => (version.lease_v, version.expiry),
+            };
+            let key = target.log_key();
+            // shared holders keep their id, exclusive leases change version
+            let new_lease_v = match target {
+                Target::Shared(_) => lease_v,
+                _ => Uuid::new_v4(),
+            };
+            let start = Instant::now();
+            // the version is only locked during each attempt, so releasing isn't blocked by retries
+            let extend = client
+                .retry_policy
+                .retry("extend", &key, expiry, || async {
+                    let mut version = version.lock().await;
+                    if version.released {
+                        return Ok(None);
+                    }
+                    let extend = target.extend(&client, lease_v, new_lease_v).await;
+                    match extend {
+                        Ok(true) => {
+                            version.lease_v = new_lease_v;
+                            version.expiry = start + client.lease_ttl();
+                            version.extending = None;
+                        }
+                        Err(_) if new_lease_v != lease_v => {
+                            version.extending = Some(new_lease_v);
+                        }
+                        _ => {}
+                    }
+                    extend.map(Some)
+                })
+                .await;
+            match extend {
+                Ok(Some(true)) => {}
+                // released
+                Ok(None) => break,
+                Ok(Some(false)) => {
+                    tracing::warn!(key, "Failed to extend lease, no longer held");
+                    let _ = lost.send(true);
+                    break;
+                }
+                // retries exhausted
+                Err(_) => {
+                    let _ = lost.send(true);
+                    break;
+                }
+            }
+        }
+    });
+}
+
+impl Drop for Held {
+    /// Asynchronously releases the leases.
+    fn drop(&mut self) {
+        if !self.released {
+            tokio::spawn(self.start_release());
+        }
+    }
+}
@@ -0,0 +1,27 @@
+use std::collections::HashMap;
+use time::OffsetDateTime;
+
+/// Info about a held lease, see [`Client::describe`](crate::Client::describe).
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct LeaseInfo {
+    /// The lease key.
+    pub key: String,
+    /// When the lease expires, unless extended.
+    pub expiry: OffsetDateTime,
+    /// Whether the lease had expired when read.
+    ///
+    /// Expired leases have not been released, e.g. if the holder crashed, and may remain
+    /// in the table for some time before being removed by dynamodb ttl.
+    pub expired: bool,
+    /// The current lease version, changes on each extension.
+    pub lease_version: String,
+    /// Fencing token, see [`Lease::fence_token`](crate::Lease::fence_token).
+    ///
+    /// `0` if the lease was written by a client predating fencing.
+    pub fence_token: u64,
+    /// The owner id of the holder, see [`ClientBuilder::owner_id`](crate::ClientBuilder::owner_id).
+    pub owner_id: Option<String>,
+    /// Lease metadata, see [`Client::with_metadata`](crate::Client::with_metadata).
+    pub metadata: HashMap<String, String>,
+}
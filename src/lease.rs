@@ -1,6 +1,10 @@
-use crate::Client;
-use std::sync::Arc;
-use tokio::sync::{Mutex, OwnedMutexGuard};
+use crate::{
+    held::{Held, Target},
+    local::LocalGuard,
+    Client, Result,
+};
+use std::collections::HashMap;
+use tokio::{sync::watch, time::Instant};
 use uuid::Uuid;
 
 /// Represents a held distributed lease & background task to
@@ -9,71 +13,105 @@ use uuid::Uuid;
 /// On drop asynchronously releases the underlying lock.
 #[derive(Debug)]
 pub struct Lease {
-    client: Client,
-    key_lease_v: Arc<(String, Mutex<Uuid>)>,
-    /// A local guard to avoid db contention for leases within the same client.
-    local_guard: Option<OwnedMutexGuard<()>>,
+    held: Held,
+    fence: u64,
+}
+
+/// The outcome of [`Lease::release`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReleaseOutcome {
+    /// The lease was still held & has now been released.
+    Released,
+    /// The lease was no longer held, e.g. it expired & was acquired elsewhere.
+    AlreadyLost,
 }
 
 impl Lease {
-    pub(crate) fn new(client: Client, key: String, lease_v: Uuid) -> Self {
-        let lease = Self {
-            client,
-            key_lease_v: Arc::new((key, Mutex::new(lease_v))),
-            local_guard: None,
-        };
+    pub(crate) fn new(
+        client: Client,
+        target: Target,
+        lease_v: Uuid,
+        expiry: Instant,
+        fence: u64,
+    ) -> Self {
+        Self {
+            held: Held::new(client, target, lease_v, expiry),
+            fence,
+        }
+    }
 
-        start_periodicly_extending(&lease);
+    pub(crate) fn with_local_guard(mut self, guard: LocalGuard) -> Self {
+        self.held.add_local_guards([guard]);
+        self
+    }
 
-        lease
+    /// Returns the fencing token of this lease.
+    ///
+    /// Fencing tokens increase each time a lease is acquired for a key, so can be passed
+    /// to downstream stores to reject writes from stale lease holders, i.e. holders with
+    /// a lower fencing token than the latest seen.
+    ///
+    /// Note: Tokens are not sequential and should only be compared for order.
+    /// Shared leases do not increase the token, they return the token of the last
+    /// exclusive lease.
+    pub fn fence_token(&self) -> u64 {
+        self.fence
     }
 
-    pub(crate) fn with_local_guard(mut self, guard: OwnedMutexGuard<()>) -> Self {
-        self.local_guard = Some(guard);
-        self
+    /// Returns `true` if this is a shared lease, see [`Client::acquire_shared`].
+    pub fn is_shared(&self) -> bool {
+        matches!(self.held.target(), Target::Shared(_))
     }
-}
 
-fn start_periodicly_extending(lease: &Lease) {
-    let key_lease_v = Arc::downgrade(&lease.key_lease_v);
-    let client = lease.client.clone();
-    tokio::spawn(async move {
-        loop {
-            tokio::time::sleep(client.extend_period).await;
-            match key_lease_v.upgrade() {
-                Some(key_lease_v) => {
-                    let mut lease_v = key_lease_v.1.lock().await;
-                    let key = key_lease_v.0.clone();
-                    match client.extend_lease(key, *lease_v).await {
-                        Ok(new_lease_v) => *lease_v = new_lease_v,
-                        // stop on error, TODO retries, logs?
-                        Err(_) => break,
-                    }
-                }
-                // lease dropped
-                None => break,
-            }
-        }
-    });
-}
+    /// Returns the id of the owner, i.e. the [`Client`], that acquired this lease.
+    pub fn owner_id(&self) -> &str {
+        self.held.client().owner_id()
+    }
+
+    /// Returns the metadata stored with this lease, see [`Client::with_metadata`] &
+    /// [`Client::acquire_with_metadata`].
+    pub fn metadata(&self) -> &HashMap<String, String> {
+        &self.held.client().metadata
+    }
 
-impl Drop for Lease {
-    /// Asynchronously releases the underlying lock.
-    fn drop(&mut self) {
-        let client = self.client.clone();
-        let key_lease_v = self.key_lease_v.clone();
+    /// Returns `true` if the lease has been lost, i.e. the background task failed to
+    /// extend it so exclusivity can no longer be guaranteed.
+    pub fn is_lost(&self) -> bool {
+        self.held.is_lost()
+    }
+
+    /// Waits until the lease has been lost, i.e. the background task failed to
+    /// extend it so exclusivity can no longer be guaranteed.
+    ///
+    /// Useful to abort critical work, e.g. using `tokio::select!`.
+    ///
+    /// ```
+    /// # async fn foo(lease: dynamodb_lease::Lease) {
+    /// # let critical_work = async {};
+    /// tokio::select! {
+    ///     _ = critical_work => {}
+    ///     _ = lease.lost() => eprintln!("lease lost, aborted critical work"),
+    /// }
+    /// # }
+    /// ```
+    pub async fn lost(&self) {
+        self.held.lost().await
+    }
 
-        // Drop local guard *before* deleting lease to avoid unfair local acquire advantage.
-        // Dropping the local_guard after deleting would be more efficient however during
-        // contention that efficiency could starve remote attempts to acquire the lease.
-        drop(self.local_guard.take());
-        client.try_clean_local_lock(key_lease_v.0.clone());
+    /// Returns a [`watch::Receiver`] of the lease's lost state.
+    ///
+    /// The value is `false` while the lease is held & changes to `true` if it is lost.
+    pub fn lost_receiver(&self) -> watch::Receiver<bool> {
+        self.held.lost_receiver()
+    }
 
-        tokio::spawn(async move {
-            let lease_v = key_lease_v.1.lock().await;
-            let key = key_lease_v.0.clone();
-            // TODO retries, logs?
-            let _ = client.delete_lease(key, *lease_v).await;
-        });
+    /// Releases the underlying lock & waits for the outcome.
+    ///
+    /// Unlike dropping, which releases asynchronously, this reports whether the lease was
+    /// still held. Once `Ok` the lease may be acquired elsewhere.
+    ///
+    /// Note: Cancelling this future will not cancel the release.
+    pub async fn release(self) -> Result<ReleaseOutcome> {
+        self.held.release().await
     }
 }
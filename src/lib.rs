@@ -22,14 +22,33 @@
 //!
 //! // Dropping the lease will asynchronously release it, so others may acquire it
 //! drop(lease);
+//!
+//! // Alternatively leases can be released explicitly, waiting for the outcome
+//! let lease = client.acquire("important-job-123").await?;
+//! lease.release().await?;
 //! # Ok(()) }
 //! ```
 
+pub mod backend;
+
+mod backoff;
 mod builder;
 mod client;
+mod election;
+mod error;
+mod held;
+mod info;
 mod lease;
 mod local;
+mod multi_lease;
+mod retry;
 
+pub use backoff::AcquireBackoff;
 pub use builder::ClientBuilder;
 pub use client::Client;
-pub use lease::Lease;
+pub use election::LeaderElection;
+pub use error::{Error, Result, WithLeaseError};
+pub use info::LeaseInfo;
+pub use lease::{Lease, ReleaseOutcome};
+pub use multi_lease::MultiLease;
+pub use retry::RetryPolicy;
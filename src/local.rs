@@ -2,24 +2,44 @@ use std::{
     collections::{hash_map::Entry, HashMap},
     sync::Arc,
 };
-use tokio::sync::{Mutex, OwnedMutexGuard, TryLockError};
+use tokio::sync::{OwnedRwLockReadGuard, OwnedRwLockWriteGuard, RwLock, TryLockError};
 
 /// Map of local key locks.
 /// These can eliminate db contention for leases acquired by the same client,
 /// ie within the same process.
+///
+/// Exclusive leases take a write lock & shared leases a read lock.
 #[derive(Debug, Clone, Default)]
-pub(crate) struct LocalLocks(Arc<std::sync::Mutex<HashMap<String, Arc<Mutex<()>>>>>);
+pub(crate) struct LocalLocks(Arc<std::sync::Mutex<HashMap<String, Arc<RwLock<()>>>>>);
+
+/// A held local key lock, unlocks on drop.
+#[derive(Debug)]
+#[allow(dead_code)] // guards are only held, not read
+pub(crate) enum LocalGuard {
+    Exclusive(OwnedRwLockWriteGuard<()>),
+    Shared(OwnedRwLockReadGuard<()>),
+}
 
 impl LocalLocks {
-    pub(crate) fn try_lock(&self, key: String) -> Result<OwnedMutexGuard<()>, TryLockError> {
-        self.key_mutex(key).try_lock_owned()
+    pub(crate) fn try_lock(&self, key: String) -> Result<LocalGuard, TryLockError> {
+        self.key_lock(key)
+            .try_write_owned()
+            .map(LocalGuard::Exclusive)
+    }
+
+    pub(crate) async fn lock(&self, key: String) -> LocalGuard {
+        LocalGuard::Exclusive(self.key_lock(key).write_owned().await)
+    }
+
+    pub(crate) fn try_lock_shared(&self, key: String) -> Result<LocalGuard, TryLockError> {
+        self.key_lock(key).try_read_owned().map(LocalGuard::Shared)
     }
 
-    pub(crate) async fn lock(&self, key: String) -> OwnedMutexGuard<()> {
-        self.key_mutex(key).lock_owned().await
+    pub(crate) async fn lock_shared(&self, key: String) -> LocalGuard {
+        LocalGuard::Shared(self.key_lock(key).read_owned().await)
     }
 
-    fn key_mutex(&self, key: String) -> Arc<Mutex<()>> {
+    fn key_lock(&self, key: String) -> Arc<RwLock<()>> {
         let mut locks = self.0.lock().unwrap();
         locks.entry(key).or_default().clone()
     }
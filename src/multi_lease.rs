@@ -0,0 +1,93 @@
+use crate::{
+    held::{Held, Target},
+    local::LocalGuard,
+    Client, ReleaseOutcome, Result,
+};
+use tokio::{sync::watch, time::Instant};
+use uuid::Uuid;
+
+/// Represents held distributed leases for multiple keys, acquired together by
+/// [`Client::try_acquire_many`], & a background task to continuously try to
+/// extend them together until dropped.
+///
+/// On drop asynchronously releases the underlying locks.
+#[derive(Debug)]
+pub struct MultiLease {
+    /// Held leases of the sorted, prefixed, keys.
+    held: Held,
+    /// Fence tokens in key order.
+    fences: Vec<u64>,
+}
+
+impl MultiLease {
+    pub(crate) fn new(
+        client: Client,
+        keys: Vec<String>,
+        lease_v: Uuid,
+        expiry: Instant,
+        fences: Vec<u64>,
+    ) -> Self {
+        Self {
+            held: Held::new(client, Target::Many(keys), lease_v, expiry),
+            fences,
+        }
+    }
+
+    pub(crate) fn with_local_guards(mut self, guards: Vec<LocalGuard>) -> Self {
+        self.held.add_local_guards(guards);
+        self
+    }
+
+    /// Returns the leased keys, in sorted order.
+    pub fn keys(&self) -> impl Iterator<Item = &str> + '_ {
+        let prefix = self.held.client().key_prefix();
+        self.held
+            .target()
+            .keys()
+            .iter()
+            .map(move |key| key.strip_prefix(prefix).unwrap_or(key))
+    }
+
+    /// Returns the fencing token of the lease for `key`, or `None` if `key` is not
+    /// one of the leased keys.
+    ///
+    /// See [`Lease::fence_token`](crate::Lease::fence_token).
+    pub fn fence_token(&self, key: &str) -> Option<u64> {
+        let key = format!("{}{key}", self.held.client().key_prefix());
+        let idx = self.held.target().keys().binary_search(&key).ok()?;
+        self.fences.get(idx).copied()
+    }
+
+    /// Returns `true` if the leases have been lost, i.e. the background task failed to
+    /// extend them so exclusivity can no longer be guaranteed.
+    ///
+    /// Leases are extended together, so if any lease is lost they all are.
+    pub fn is_lost(&self) -> bool {
+        self.held.is_lost()
+    }
+
+    /// Waits until the leases have been lost, i.e. the background task failed to
+    /// extend them so exclusivity can no longer be guaranteed.
+    ///
+    /// See [`Lease::lost`](crate::Lease::lost).
+    pub async fn lost(&self) {
+        self.held.lost().await
+    }
+
+    /// Returns a [`watch::Receiver`] of the leases' lost state.
+    ///
+    /// The value is `false` while the leases are held & changes to `true` if they are lost.
+    pub fn lost_receiver(&self) -> watch::Receiver<bool> {
+        self.held.lost_receiver()
+    }
+
+    /// Releases the underlying locks together & waits for the outcome.
+    ///
+    /// If any lease was no longer held [`ReleaseOutcome::AlreadyLost`] is returned,
+    /// after releasing the leases that were still held individually.
+    ///
+    /// Note: Cancelling this future will not cancel the release.
+    pub async fn release(self) -> Result<ReleaseOutcome> {
+        self.held.release().await
+    }
+}
@@ -0,0 +1,127 @@
+use std::{fmt, future::Future, time::Duration};
+use tokio::time::Instant;
+
+/// Retry policy for background lease db operations, i.e. extending a held lease
+/// & releasing a lease on drop.
+///
+/// Failed attempts are retried with exponential backoff & jitter up to a max number
+/// of attempts. Retries also stop once the lease has expired, as after that point the
+/// lease cannot be relied upon anyway.
+///
+/// # Example
+/// ```
+/// # use std::time::Duration;
+/// # use dynamodb_lease::RetryPolicy;
+/// let policy = RetryPolicy::default()
+///     .max_attempts(5)
+///     .initial_backoff(Duration::from_millis(50))
+///     .max_backoff(Duration::from_secs(1));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 10,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Returns a policy that never retries, i.e. only a single attempt is made.
+    pub fn no_retries() -> Self {
+        Self::default().max_attempts(1)
+    }
+
+    /// Sets the max number of attempts, including the first.
+    ///
+    /// Default `10`.
+    ///
+    /// # Panics
+    /// Panics if zero.
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        assert!(max_attempts > 0, "must be greater than zero");
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Sets the backoff before the first retry. Each subsequent retry doubles this up to
+    /// [`RetryPolicy::max_backoff`]. Jitter is applied so actual backoffs will be between
+    /// half & all of this value.
+    ///
+    /// Default `100ms`.
+    pub fn initial_backoff(mut self, backoff: Duration) -> Self {
+        self.initial_backoff = backoff;
+        self
+    }
+
+    /// Sets the max backoff between retries.
+    ///
+    /// Default `5s`.
+    pub fn max_backoff(mut self, backoff: Duration) -> Self {
+        self.max_backoff = backoff;
+        self
+    }
+
+    /// Returns the jittered backoff to wait after the given failed `attempt` (starting at 1).
+    fn backoff(&self, attempt: u32) -> Duration {
+        let backoff = self
+            .initial_backoff
+            .saturating_mul(2_u32.saturating_pow(attempt - 1))
+            .min(self.max_backoff);
+        // "equal jitter", half fixed & half random
+        backoff / 2 + (backoff / 2).mul_f64(fastrand::f64())
+    }
+
+    /// Runs `op` until it succeeds, retrying according to this policy. Will not retry if
+    /// the next attempt would start after `deadline`.
+    pub(crate) async fn retry<T, E, F, Fut>(
+        &self,
+        action: &str,
+        key: &str,
+        deadline: Instant,
+        mut op: F,
+    ) -> Result<T, E>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        E: fmt::Display,
+    {
+        let mut attempt = 1;
+        loop {
+            let err = match op().await {
+                Ok(out) => return Ok(out),
+                Err(err) => err,
+            };
+
+            let backoff = self.backoff(attempt);
+            if attempt >= self.max_attempts {
+                tracing::error!(key, attempt, "Failed to {action} lease, giving up: {err}");
+                return Err(err);
+            }
+            if Instant::now() + backoff >= deadline {
+                tracing::error!(
+                    key,
+                    attempt,
+                    "Failed to {action} lease, giving up as lease will have expired: {err}"
+                );
+                return Err(err);
+            }
+
+            tracing::warn!(
+                key,
+                attempt,
+                "Failed to {action} lease, retrying in {backoff:?}: {err}"
+            );
+            tokio::time::sleep(backoff).await;
+            attempt += 1;
+        }
+    }
+}
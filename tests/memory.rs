@@ -0,0 +1,1011 @@
+//! Tests using the in-memory backend, requires feature "test-util".
+use dynamodb_lease::{
+    backend::{FaultyBackend, LeaseBackend, MemoryBackend, Operation, PutLease, PutOutcome},
+    AcquireBackoff, Client, Error, LeaderElection, LeaseInfo, ReleaseOutcome, RetryPolicy,
+    WithLeaseError,
+};
+use std::{collections::HashMap, time::Duration};
+use tokio_stream::StreamExt;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+async fn memory_client(backend: &MemoryBackend) -> Client {
+    Client::builder()
+        .lease_ttl_seconds(10)
+        .clock_skew_margin_seconds(1)
+        .retry_policy(RetryPolicy::no_retries())
+        .build_with_backend(backend.clone())
+        .await
+        .unwrap()
+}
+
+/// Client using a `FaultyBackend` wrapping `backend`.
+async fn faulty_client(backend: &MemoryBackend) -> (Client, FaultyBackend<MemoryBackend>) {
+    let faulty = FaultyBackend::new(backend.clone());
+    let client = Client::builder()
+        .lease_ttl_seconds(10)
+        .clock_skew_margin_seconds(1)
+        .build_with_backend(faulty.clone())
+        .await
+        .unwrap();
+    (client, faulty)
+}
+
+#[tokio::test(start_paused = true)]
+async fn acquire_release() {
+    let backend = MemoryBackend::default();
+    let client = memory_client(&backend).await;
+    let other_client = memory_client(&backend).await;
+
+    let lease = client.try_acquire("key").await.unwrap().unwrap();
+    assert!(other_client.try_acquire("key").await.unwrap().is_none());
+
+    let info = other_client.describe("key").await.unwrap().unwrap();
+    assert_eq!(info.fence_token, lease.fence_token());
+    assert!(!info.expired);
+
+    assert_eq!(lease.release().await.unwrap(), ReleaseOutcome::Released);
+    let lease = other_client.try_acquire("key").await.unwrap().unwrap();
+    assert_eq!(lease.fence_token(), info.fence_token + 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn acquire_with_metadata() {
+    let backend = MemoryBackend::default();
+    let client = memory_client(&backend).await;
+    let metadata = |job: &str| HashMap::from([("job".to_owned(), job.to_owned())]);
+
+    let reindex = client
+        .acquire_with_metadata("reindex", metadata("reindex"))
+        .await
+        .unwrap();
+    let backup = client
+        .try_acquire_with_metadata("backup", metadata("backup"))
+        .await
+        .unwrap()
+        .unwrap();
+    let plain = client.acquire("plain").await.unwrap();
+
+    assert_eq!(reindex.metadata(), &metadata("reindex"));
+    assert_eq!(backup.metadata(), &metadata("backup"));
+    assert!(plain.metadata().is_empty());
+    let info = client.describe("backup").await.unwrap().unwrap();
+    assert_eq!(info.metadata, metadata("backup"));
+}
+
+#[tokio::test(start_paused = true)]
+async fn extends_beyond_ttl() {
+    let backend = MemoryBackend::default();
+    let client = memory_client(&backend).await;
+
+    let lease = client.acquire("key").await.unwrap();
+    tokio::time::sleep(Duration::from_secs(60)).await;
+
+    assert!(!lease.is_lost());
+    assert!(client.try_acquire("key").await.unwrap().is_none());
+}
+
+#[tokio::test(start_paused = true)]
+async fn extend_failure_expires() {
+    let backend = MemoryBackend::default();
+    let client = memory_client(&backend).await;
+    let other_client = memory_client(&backend).await;
+
+    let lease = client.acquire("key").await.unwrap();
+    backend.fail_next_extends(1);
+
+    tokio::time::timeout(Duration::from_secs(10), lease.lost())
+        .await
+        .expect("lease should be lost");
+
+    // still exclusive until expiry + clock skew margin
+    tokio::time::sleep(Duration::from_secs(4)).await;
+    assert!(other_client.try_acquire("key").await.unwrap().is_none());
+
+    tokio::time::sleep(Duration::from_secs(3)).await;
+    let info = other_client.describe("key").await.unwrap().unwrap();
+    assert!(info.expired);
+    other_client
+        .try_acquire("key")
+        .await
+        .unwrap()
+        .expect("should acquire expired lease");
+
+    assert_eq!(lease.release().await.unwrap(), ReleaseOutcome::AlreadyLost);
+}
+
+#[tokio::test(start_paused = true)]
+async fn release_failure() {
+    let backend = MemoryBackend::default();
+    let client = memory_client(&backend).await;
+
+    let lease = client.acquire("key").await.unwrap();
+    backend.fail_next_releases(1);
+
+    assert!(matches!(lease.release().await, Err(Error::Backend(_))));
+    assert!(client.try_acquire("key").await.unwrap().is_none());
+}
+
+#[tokio::test(start_paused = true)]
+async fn shared() {
+    let backend = MemoryBackend::default();
+    let client = memory_client(&backend).await;
+    let other_client = memory_client(&backend).await;
+
+    let shared = client.acquire_shared("key").await.unwrap();
+    let _shared2 = other_client.acquire_shared("key").await.unwrap();
+    assert!(other_client.try_acquire("key").await.unwrap().is_none());
+
+    // shared holders extend their own leases
+    tokio::time::sleep(Duration::from_secs(60)).await;
+    assert!(!shared.is_lost());
+    assert!(other_client.try_acquire("key").await.unwrap().is_none());
+}
+
+/// Lost access to db after acquiring lease, recovering before the lease expires.
+#[tokio::test(start_paused = true)]
+async fn faulty_extend_retried() {
+    let backend = MemoryBackend::default();
+    let (client, faulty) = faulty_client(&backend).await;
+
+    let lease = client.acquire("key").await.unwrap();
+    faulty.fail_next(Operation::Extend, 3);
+
+    tokio::time::sleep(Duration::from_secs(30)).await;
+    assert!(!lease.is_lost());
+    assert_eq!(lease.release().await.unwrap(), ReleaseOutcome::Released);
+}
+
+/// Lost access to db after acquiring lease, until the lease expires.
+#[tokio::test(start_paused = true)]
+async fn faulty_extend_lost() {
+    let backend = MemoryBackend::default();
+    let (client, faulty) = faulty_client(&backend).await;
+    let other_client = memory_client(&backend).await;
+
+    let lease = client.acquire("key").await.unwrap();
+    faulty.fail_next(Operation::Extend, usize::MAX);
+    faulty.fail_next(Operation::Release, usize::MAX);
+
+    tokio::time::timeout(Duration::from_secs(10), lease.lost())
+        .await
+        .expect("lease should be lost");
+    drop(lease);
+
+    // released once expired, after the clock skew margin
+    tokio::time::sleep(Duration::from_secs(6)).await;
+    other_client
+        .try_acquire("key")
+        .await
+        .unwrap()
+        .expect("should acquire expired lease");
+}
+
+/// Released while extension is being retried, after an extension applied but failed to respond.
+#[tokio::test(start_paused = true)]
+async fn faulty_release_while_extend_retried() {
+    let backend = MemoryBackend::default();
+    let (client, faulty) = faulty_client(&backend).await;
+    let other_client = memory_client(&backend).await;
+
+    let lease = client.acquire("key").await.unwrap();
+    faulty.lose_response_next(Operation::Extend, 1);
+    tokio::time::sleep(Duration::from_millis(5010)).await;
+    faulty.fail_next(Operation::Extend, usize::MAX);
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    // does not wait for extension retries to give up
+    let release = tokio::time::timeout(Duration::from_millis(10), lease.release());
+    let released = release
+        .await
+        .expect("release should not wait for extension");
+    assert_eq!(released.unwrap(), ReleaseOutcome::Released);
+    other_client
+        .try_acquire("key")
+        .await
+        .unwrap()
+        .expect("should acquire released lease");
+}
+
+#[tokio::test(start_paused = true)]
+async fn faulty_takeover() {
+    let backend = MemoryBackend::default();
+    let (client, faulty) = faulty_client(&backend).await;
+
+    let lease = client.acquire("key").await.unwrap();
+    faulty.condition_fail_next(Operation::Extend, 1);
+    tokio::time::timeout(Duration::from_secs(10), lease.lost())
+        .await
+        .expect("lease should be lost");
+
+    faulty.condition_fail_next(Operation::Release, 1);
+    assert_eq!(lease.release().await.unwrap(), ReleaseOutcome::AlreadyLost);
+}
+
+/// Extend & release applied in the db, but the responses were lost so are retried.
+#[tokio::test(start_paused = true)]
+async fn faulty_lost_response() {
+    let backend = MemoryBackend::default();
+    let (client, faulty) = faulty_client(&backend).await;
+    let other_client = memory_client(&backend).await;
+
+    let lease = client.acquire("key").await.unwrap();
+    faulty.lose_response_next(Operation::Extend, 1);
+    tokio::time::sleep(Duration::from_secs(30)).await;
+    assert!(!lease.is_lost());
+    assert!(other_client.try_acquire("key").await.unwrap().is_none());
+
+    faulty.lose_response_next(Operation::Release, 1);
+    assert_eq!(lease.release().await.unwrap(), ReleaseOutcome::Released);
+    other_client
+        .try_acquire("key")
+        .await
+        .unwrap()
+        .expect("should acquire released lease");
+
+    let leases = client.try_acquire_many(["a", "b"]).await.unwrap().unwrap();
+    faulty.lose_response_next(Operation::Release, 1);
+    assert_eq!(leases.release().await.unwrap(), ReleaseOutcome::Released);
+}
+
+#[tokio::test(start_paused = true)]
+async fn faulty_latency() {
+    let backend = MemoryBackend::default();
+    let (client, faulty) = faulty_client(&backend).await;
+
+    faulty.set_latency(Operation::Acquire, Duration::from_secs(2));
+    let start = tokio::time::Instant::now();
+    let _lease = client.acquire("key").await.unwrap();
+    assert!(start.elapsed() >= Duration::from_secs(2));
+
+    faulty.fail_next(Operation::Describe, 1);
+    assert!(matches!(
+        client.describe("key").await,
+        Err(Error::Backend(_))
+    ));
+    assert!(client.describe("key").await.unwrap().is_some());
+}
+
+#[tokio::test(start_paused = true)]
+async fn with_lease() {
+    let backend = MemoryBackend::default();
+    let client = memory_client(&backend).await;
+
+    let fence = client
+        .with_lease("key", |lease| {
+            Box::pin(async move {
+                tokio::time::sleep(Duration::from_secs(30)).await;
+                lease.fence_token()
+            })
+        })
+        .await
+        .unwrap();
+
+    // released before returning
+    let lease = client.try_acquire("key").await.unwrap().unwrap();
+    assert_eq!(lease.fence_token(), fence + 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn with_lease_lost() {
+    let backend = MemoryBackend::default();
+    let (client, faulty) = faulty_client(&backend).await;
+
+    faulty.condition_fail_next(Operation::Extend, 1);
+    let result = client
+        .with_lease("key", |_| {
+            Box::pin(async {
+                tokio::time::sleep(Duration::from_secs(30)).await;
+                panic!("should be aborted");
+            })
+        })
+        .await;
+    assert!(matches!(result, Err(WithLeaseError::LeaseLost)));
+
+    // released before returning
+    assert!(client.try_acquire("key").await.unwrap().is_some());
+}
+
+#[tokio::test(start_paused = true)]
+async fn with_lease_owned() {
+    let backend = MemoryBackend::default();
+    let client = memory_client(&backend).await;
+
+    // the future need not be `Send`
+    let fence = std::rc::Rc::new(std::cell::Cell::new(0));
+    client
+        .with_lease_owned("key", |lease| {
+            let (fence, token) = (fence.clone(), lease.fence_token());
+            async move {
+                tokio::time::sleep(Duration::from_secs(30)).await;
+                fence.set(token);
+            }
+        })
+        .await
+        .unwrap();
+
+    let lease = client.try_acquire("key").await.unwrap().unwrap();
+    assert_eq!(lease.fence_token(), fence.get() + 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn leader_election() {
+    let backend = MemoryBackend::default();
+    let (client, faulty) = faulty_client(&backend).await;
+    let other_client = Client::builder()
+        .owner_id("other")
+        .build_with_backend(backend.clone())
+        .await
+        .unwrap();
+
+    let election = LeaderElection::new(client.clone(), "leader");
+    election
+        .leader_receiver()
+        .wait_for(|is_leader| *is_leader)
+        .await
+        .unwrap();
+    let other_election = LeaderElection::new(other_client, "leader");
+    tokio::time::sleep(Duration::from_secs(5)).await;
+    assert!(!other_election.is_leader());
+    assert_eq!(
+        other_election.leader_owner_id().await.unwrap().as_deref(),
+        Some(client.owner_id())
+    );
+
+    // step down, other becomes leader
+    election.step_down(Duration::from_secs(5)).await.unwrap();
+    assert!(!election.is_leader());
+    other_election
+        .leader_receiver()
+        .wait_for(|is_leader| *is_leader)
+        .await
+        .unwrap();
+    assert_eq!(
+        election.leader_owner_id().await.unwrap().as_deref(),
+        Some("other")
+    );
+
+    // leader dropped, first re-elected
+    drop(other_election);
+    election
+        .leader_receiver()
+        .wait_for(|is_leader| *is_leader)
+        .await
+        .unwrap();
+
+    // leadership lost & re-acquired
+    let mut leader = election.leader_receiver();
+    faulty.condition_fail_next(Operation::Extend, 1);
+    faulty.condition_fail_next(Operation::Acquire, 1);
+    leader.wait_for(|is_leader| !*is_leader).await.unwrap();
+    leader.wait_for(|is_leader| *is_leader).await.unwrap();
+}
+
+#[tokio::test(start_paused = true)]
+async fn leader_election_paused() {
+    let backend = MemoryBackend::default();
+    let client = memory_client(&backend).await;
+
+    let election = LeaderElection::new(client, "leader");
+    let mut leader = election.leader_receiver();
+    leader.wait_for(|is_leader| *is_leader).await.unwrap();
+    election.step_down(Duration::from_secs(60)).await.unwrap();
+
+    // handled while paused
+    tokio::time::timeout(
+        Duration::from_secs(1),
+        election.step_down(Duration::from_secs(60)),
+    )
+    .await
+    .expect("step down should not wait for the pause")
+    .unwrap();
+
+    // campaigning stops while paused
+    assert!(!*leader.borrow_and_update());
+    drop(election);
+    tokio::time::timeout(Duration::from_secs(1), leader.changed())
+        .await
+        .expect("campaign should stop")
+        .expect_err("campaign should stop");
+}
+
+#[tokio::test(start_paused = true)]
+async fn fair_order() {
+    let backend = MemoryBackend::default();
+    let fair_client = |cooldown| {
+        Client::builder()
+            .lease_ttl_seconds(10)
+            .acquire_cooldown(cooldown)
+            .fair(true)
+            .build_with_backend(backend.clone())
+    };
+    let client = fair_client(Duration::from_secs(1)).await.unwrap();
+    let slow_client = fair_client(Duration::from_secs(5)).await.unwrap();
+    let fast_client = fair_client(Duration::from_millis(10)).await.unwrap();
+
+    let lease = client.acquire("key").await.unwrap();
+    let first = tokio::spawn(async move { slow_client.acquire("key").await.unwrap() });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let second = tokio::spawn(async move { fast_client.acquire("key").await.unwrap() });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // fast client polls more often but waits for the slow client's turn
+    lease.release().await.unwrap();
+    let first = first.await.unwrap();
+    assert!(!second.is_finished());
+
+    first.release().await.unwrap();
+    second.await.unwrap();
+}
+
+#[tokio::test(start_paused = true)]
+async fn fair_abandoned_ticket_expires() {
+    let backend = MemoryBackend::default();
+    let client = Client::builder()
+        .lease_ttl_seconds(10)
+        .clock_skew_margin_seconds(1)
+        .fair(true)
+        .build_with_backend(backend.clone())
+        .await
+        .unwrap();
+
+    // simulate a waiter that crashed
+    backend
+        .enqueue("key", Uuid::new_v4(), Duration::from_secs(10))
+        .await
+        .unwrap();
+    assert!(client.try_acquire("key").await.unwrap().is_none());
+
+    let start = tokio::time::Instant::now();
+    client.acquire("key").await.unwrap();
+    assert!(start.elapsed() >= Duration::from_secs(10));
+}
+
+#[tokio::test(start_paused = true)]
+async fn fair_queue_kept_after_holder_crashed() {
+    let backend = MemoryBackend::default();
+    let fair = || {
+        Client::builder()
+            .lease_ttl_seconds(10)
+            .clock_skew_margin_seconds(1)
+            .fair(true)
+    };
+    let faulty = FaultyBackend::new(backend.clone());
+    let holder = fair().build_with_backend(faulty.clone()).await.unwrap();
+    let first_client = fair().build_with_backend(backend.clone()).await.unwrap();
+    let second_client = fair().build_with_backend(backend.clone()).await.unwrap();
+    let late_client = fair().build_with_backend(backend.clone()).await.unwrap();
+
+    // simulate a holder that crashed, never extending or releasing
+    let _lease = holder.acquire("key").await.unwrap();
+    faulty.fail_next(Operation::Extend, usize::MAX);
+    faulty.fail_next(Operation::Release, usize::MAX);
+
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    let first = tokio::spawn(async move { first_client.acquire("key").await.unwrap() });
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    let second = tokio::spawn(async move { second_client.acquire("key").await.unwrap() });
+
+    // the lease has expired, but the item is kept by ttl while tickets are queued
+    tokio::time::sleep(Duration::from_millis(8500)).await;
+    backend.remove_ttl_expired();
+    assert!(late_client.try_acquire("key").await.unwrap().is_none());
+
+    let first = first.await.unwrap();
+    assert!(!second.is_finished());
+    first.release().await.unwrap();
+    second.await.unwrap();
+}
+
+#[tokio::test(start_paused = true)]
+async fn acquire_backoff_capped_to_expiry() {
+    let backend = MemoryBackend::default();
+    let client = memory_client(&backend).await;
+    let waiting_client = Client::builder()
+        .lease_ttl_seconds(10)
+        .clock_skew_margin_seconds(1)
+        .acquire_backoff(AcquireBackoff::Exponential {
+            initial: Duration::from_secs(1),
+            max: Duration::from_secs(3600),
+        })
+        .build_with_backend(backend.clone())
+        .await
+        .unwrap();
+
+    let lease = client.acquire("key").await.unwrap();
+    let waiter = tokio::spawn(async move { waiting_client.acquire("key").await.unwrap() });
+
+    tokio::time::sleep(Duration::from_secs(120)).await;
+    lease.release().await.unwrap();
+    let start = tokio::time::Instant::now();
+
+    // waits are capped to the holder's expiry + clock skew margin, instead of growing to minutes
+    waiter.await.unwrap();
+    assert!(start.elapsed() <= Duration::from_secs(11));
+}
+
+/// A zero min wait would spin or never grow.
+#[test]
+#[should_panic(expected = "min wait must be greater than zero")]
+fn acquire_backoff_zero_base() {
+    let _ = Client::builder().acquire_backoff(AcquireBackoff::DecorrelatedJitter {
+        base: Duration::ZERO,
+        max: Duration::from_secs(10),
+    });
+}
+
+#[test]
+#[should_panic(expected = "max wait must be at least the min wait")]
+fn acquire_backoff_max_below_initial() {
+    let _ = Client::builder().acquire_backoff(AcquireBackoff::Exponential {
+        initial: Duration::from_secs(10),
+        max: Duration::from_secs(1),
+    });
+}
+
+#[tokio::test(start_paused = true)]
+async fn acquire_permit_backoff_capped_to_expiry() {
+    let backend = MemoryBackend::default();
+    let client = memory_client(&backend).await;
+    let waiting_client = Client::builder()
+        .lease_ttl_seconds(10)
+        .clock_skew_margin_seconds(1)
+        .acquire_backoff(AcquireBackoff::Exponential {
+            initial: Duration::from_secs(1),
+            max: Duration::from_secs(3600),
+        })
+        .build_with_backend(backend.clone())
+        .await
+        .unwrap();
+
+    let _permit = client.acquire_permit("key", 2).await.unwrap();
+    let permit = client.acquire_permit("key", 2).await.unwrap();
+    let waiter =
+        tokio::spawn(async move { waiting_client.acquire_permit("key", 2).await.unwrap() });
+
+    tokio::time::sleep(Duration::from_secs(120)).await;
+    permit.release().await.unwrap();
+    let start = tokio::time::Instant::now();
+
+    // waits are capped to the holders' expiry + clock skew margin, instead of growing to minutes
+    waiter.await.unwrap();
+    assert!(start.elapsed() <= Duration::from_secs(11));
+}
+
+#[tokio::test(start_paused = true)]
+async fn acquire_until_cancelled() {
+    let backend = MemoryBackend::default();
+    let client = memory_client(&backend).await;
+    let other_client = memory_client(&backend).await;
+
+    let _lease = client.acquire("key").await.unwrap();
+    let cancel = CancellationToken::new();
+    let waiter = tokio::spawn({
+        let cancel = cancel.clone();
+        async move { other_client.acquire_until("key", cancel).await }
+    });
+    tokio::time::sleep(Duration::from_secs(5)).await;
+
+    cancel.cancel();
+    assert!(matches!(waiter.await.unwrap(), Err(Error::Cancelled)));
+}
+
+/// Cancelled while the put is in flight, the acquired lease is released.
+#[tokio::test(start_paused = true)]
+async fn acquire_cancelled_in_flight() {
+    let backend = MemoryBackend::default();
+    let (client, faulty) = faulty_client(&backend).await;
+    let other_client = memory_client(&backend).await;
+
+    faulty.set_latency(Operation::Acquire, Duration::from_secs(2));
+    tokio::time::timeout(Duration::from_secs(1), client.acquire("key"))
+        .await
+        .expect_err("should time out");
+
+    tokio::time::sleep(Duration::from_secs(2)).await;
+    other_client
+        .try_acquire("key")
+        .await
+        .unwrap()
+        .expect("should acquire released lease");
+}
+
+#[tokio::test(start_paused = true)]
+async fn fair_enqueue_cancelled_in_flight() {
+    let backend = MemoryBackend::default();
+    let faulty = FaultyBackend::new(backend.clone());
+    let client = Client::builder()
+        .lease_ttl_seconds(10)
+        .fair(true)
+        .build_with_backend(faulty.clone())
+        .await
+        .unwrap();
+    let other_client = Client::builder()
+        .lease_ttl_seconds(10)
+        .fair(true)
+        .build_with_backend(backend.clone())
+        .await
+        .unwrap();
+
+    faulty.set_latency(Operation::Enqueue, Duration::from_secs(2));
+    tokio::time::timeout(Duration::from_secs(1), client.acquire("key"))
+        .await
+        .expect_err("should time out");
+
+    // the ticket written after cancellation is dequeued, not left to block the queue
+    tokio::time::sleep(Duration::from_secs(2)).await;
+    other_client
+        .try_acquire("key")
+        .await
+        .unwrap()
+        .expect("should acquire without waiting for the abandoned ticket to expire");
+}
+
+#[tokio::test(start_paused = true)]
+async fn key_prefix() {
+    let backend = MemoryBackend::default();
+    let client = Client::builder()
+        .key_prefix("svc-a/")
+        .build_with_backend(backend.clone())
+        .await
+        .unwrap();
+    let other_client = memory_client(&backend).await;
+
+    let _lease = client.try_acquire("job-1").await.unwrap().unwrap();
+    let _other_lease = other_client.try_acquire("job-1").await.unwrap().unwrap();
+    // namespaces are scoped clones, also prefixing local locks
+    let _ns_lease = client
+        .namespace("x/")
+        .try_acquire("job-1")
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(client.try_acquire("job-1").await.unwrap().is_none());
+
+    let info = client.describe("job-1").await.unwrap().unwrap();
+    assert_eq!(info.key, "job-1");
+    let info = other_client.describe("svc-a/job-1").await.unwrap().unwrap();
+    assert_eq!(info.key, "svc-a/job-1");
+
+    let mut keys: Vec<_> = client
+        .list_leases()
+        .map(|info| info.unwrap().key)
+        .collect()
+        .await;
+    keys.sort();
+    assert_eq!(keys, ["job-1", "x/job-1"]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn try_acquire_many() {
+    let backend = MemoryBackend::default();
+    let client = memory_client(&backend).await;
+    let other_client = memory_client(&backend).await;
+
+    let leases = client
+        .try_acquire_many(["b", "a", "b"])
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(leases.keys().collect::<Vec<_>>(), ["a", "b"]);
+    let info = other_client.describe("a").await.unwrap().unwrap();
+    assert_eq!(leases.fence_token("a"), Some(info.fence_token));
+    assert_eq!(leases.fence_token("c"), None);
+
+    // all-or-nothing, "c" is not acquired
+    assert!(other_client
+        .try_acquire_many(["b", "c"])
+        .await
+        .unwrap()
+        .is_none());
+    assert!(client.try_acquire("a").await.unwrap().is_none());
+    let lease_c = other_client.try_acquire("c").await.unwrap().unwrap();
+    assert!(client.try_acquire_many(["a", "c"]).await.unwrap().is_none());
+
+    // extended together
+    tokio::time::sleep(Duration::from_secs(60)).await;
+    assert!(!leases.is_lost());
+    assert!(other_client.try_acquire("b").await.unwrap().is_none());
+
+    assert_eq!(leases.release().await.unwrap(), ReleaseOutcome::Released);
+    drop(lease_c);
+    assert!(other_client
+        .try_acquire_many(["a", "b", "c"])
+        .await
+        .unwrap()
+        .is_some());
+}
+
+#[tokio::test(start_paused = true)]
+async fn try_acquire_many_lost() {
+    let backend = MemoryBackend::default();
+    let (client, faulty) = faulty_client(&backend).await;
+
+    let leases = client.try_acquire_many(["a", "b"]).await.unwrap().unwrap();
+    faulty.condition_fail_next(Operation::Extend, 1);
+    tokio::time::timeout(Duration::from_secs(10), leases.lost())
+        .await
+        .expect("leases should be lost");
+
+    // release falls back to releasing each lease still held
+    faulty.condition_fail_next(Operation::Release, 1);
+    assert_eq!(leases.release().await.unwrap(), ReleaseOutcome::AlreadyLost);
+    assert!(client.try_acquire("a").await.unwrap().is_some());
+}
+
+/// Leases put in the db, but the response, e.g. reading the fence tokens, failed.
+#[tokio::test(start_paused = true)]
+async fn try_acquire_many_lost_response() {
+    let backend = MemoryBackend::default();
+    let (client, faulty) = faulty_client(&backend).await;
+    let other_client = memory_client(&backend).await;
+
+    faulty.lose_response_next(Operation::Acquire, 1);
+    assert!(matches!(
+        client.try_acquire_many(["a", "b"]).await,
+        Err(Error::Backend(_))
+    ));
+
+    // released, not left held until expiry
+    other_client
+        .try_acquire_many(["a", "b"])
+        .await
+        .unwrap()
+        .expect("should acquire released leases");
+}
+
+async fn hierarchical_client(backend: &MemoryBackend) -> Client {
+    Client::builder()
+        .lease_ttl_seconds(10)
+        .clock_skew_margin_seconds(1)
+        .hierarchical(true)
+        .build_with_backend(backend.clone())
+        .await
+        .unwrap()
+}
+
+#[tokio::test(start_paused = true)]
+async fn hierarchical() {
+    let backend = MemoryBackend::default();
+    let client = hierarchical_client(&backend).await;
+    let other_client = hierarchical_client(&backend).await;
+
+    let lease = client.acquire("tenant/42/invoice/7").await.unwrap();
+    // ancestors conflict
+    assert!(other_client
+        .try_acquire("tenant/42")
+        .await
+        .unwrap()
+        .is_none());
+    assert!(other_client.try_acquire("tenant").await.unwrap().is_none());
+    // siblings do not
+    let sibling = other_client
+        .try_acquire("tenant/42/invoice/8")
+        .await
+        .unwrap()
+        .unwrap();
+
+    // intents are extended with the lease
+    tokio::time::sleep(Duration::from_secs(60)).await;
+    assert!(other_client
+        .try_acquire("tenant/42")
+        .await
+        .unwrap()
+        .is_none());
+
+    assert_eq!(lease.release().await.unwrap(), ReleaseOutcome::Released);
+    assert!(other_client
+        .try_acquire("tenant/42")
+        .await
+        .unwrap()
+        .is_none());
+    assert_eq!(sibling.release().await.unwrap(), ReleaseOutcome::Released);
+    let ancestor = other_client
+        .try_acquire("tenant/42")
+        .await
+        .unwrap()
+        .unwrap();
+
+    // descendants conflict
+    assert!(client
+        .try_acquire("tenant/42/invoice/7")
+        .await
+        .unwrap()
+        .is_none());
+    assert!(client.try_acquire("tenant/43").await.unwrap().is_some());
+    drop(ancestor);
+}
+
+#[tokio::test(start_paused = true)]
+async fn hierarchical_intents_expire() {
+    let backend = MemoryBackend::default();
+    let faulty = FaultyBackend::new(backend.clone());
+    let client = Client::builder()
+        .lease_ttl_seconds(10)
+        .clock_skew_margin_seconds(1)
+        .hierarchical(true)
+        .build_with_backend(faulty.clone())
+        .await
+        .unwrap();
+    let other_client = hierarchical_client(&backend).await;
+
+    let lease = client.acquire("tenant/42").await.unwrap();
+    faulty.fail_next(Operation::Extend, usize::MAX);
+    faulty.fail_next(Operation::Release, usize::MAX);
+    tokio::time::timeout(Duration::from_secs(10), lease.lost())
+        .await
+        .expect("lease should be lost");
+    drop(lease);
+    assert!(other_client.try_acquire("tenant").await.unwrap().is_none());
+
+    // expired intents no longer conflict, after the clock skew margin
+    tokio::time::sleep(Duration::from_secs(6)).await;
+    other_client
+        .try_acquire("tenant")
+        .await
+        .unwrap()
+        .expect("should acquire ancestor of expired lease");
+}
+
+/// Shared leases, permits, multi-key leases & fair mode would not conflict with leases of
+/// ancestors & descendants, so are rejected.
+#[tokio::test(start_paused = true)]
+async fn hierarchical_intents_keep_ancestors() {
+    let backend = MemoryBackend::default();
+    let long_client = Client::builder()
+        .lease_ttl_seconds(60)
+        .hierarchical(true)
+        .build_with_backend(backend.clone())
+        .await
+        .unwrap();
+    let faulty = FaultyBackend::new(backend.clone());
+    let short_client = Client::builder()
+        .lease_ttl_seconds(10)
+        .clock_skew_margin_seconds(1)
+        .hierarchical(true)
+        .build_with_backend(faulty.clone())
+        .await
+        .unwrap();
+    let other_client = hierarchical_client(&backend).await;
+
+    let _lease = long_client.acquire("tenant/42/invoice/7").await.unwrap();
+    // a shorter intent, that is then never extended, must not shorten the ancestor ttl
+    let _short_lease = short_client.acquire("tenant/42/invoice/8").await.unwrap();
+    faulty.fail_next(Operation::Extend, usize::MAX);
+    faulty.fail_next(Operation::Release, usize::MAX);
+
+    tokio::time::sleep(Duration::from_secs(15)).await;
+    backend.remove_ttl_expired();
+    assert!(other_client
+        .try_acquire("tenant/42")
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[tokio::test(start_paused = true)]
+async fn hierarchical_max_depth() {
+    let backend = MemoryBackend::default();
+    let client = hierarchical_client(&backend).await;
+
+    let path = |depth: usize| vec!["a"; depth].join("/");
+    let lease = client.try_acquire(path(100)).await.unwrap();
+    assert!(lease.is_some());
+    drop(lease);
+
+    let err = client.try_acquire(path(101)).await.unwrap_err();
+    assert!(matches!(err, Error::Unsupported(_)), "{err:?}");
+}
+
+#[tokio::test(start_paused = true)]
+async fn hierarchical_unsupported() {
+    let backend = MemoryBackend::default();
+    let client = hierarchical_client(&backend).await;
+
+    let _lease = client.acquire("tenant/42").await.unwrap();
+    assert!(matches!(
+        client.try_acquire_shared("tenant").await,
+        Err(Error::Unsupported(_))
+    ));
+    assert!(matches!(
+        client.acquire_shared("tenant/42/invoice/7").await,
+        Err(Error::Unsupported(_))
+    ));
+    assert!(matches!(
+        client.try_acquire_permit("tenant", 2).await,
+        Err(Error::Unsupported(_))
+    ));
+    assert!(matches!(
+        client.try_acquire_many(["tenant", "other"]).await,
+        Err(Error::Unsupported(_))
+    ));
+
+    let fair_client = Client::builder()
+        .fair(true)
+        .hierarchical(true)
+        .build_with_backend(backend.clone())
+        .await;
+    assert!(matches!(fair_client, Err(Error::Unsupported(_))));
+}
+
+/// Lease & intents put in the db, but the response, e.g. reading the fence token, failed.
+#[tokio::test(start_paused = true)]
+async fn hierarchical_lost_response() {
+    let backend = MemoryBackend::default();
+    let faulty = FaultyBackend::new(backend.clone());
+    let client = Client::builder()
+        .lease_ttl_seconds(10)
+        .hierarchical(true)
+        .build_with_backend(faulty.clone())
+        .await
+        .unwrap();
+    let other_client = hierarchical_client(&backend).await;
+
+    faulty.lose_response_next(Operation::Acquire, 1);
+    assert!(matches!(
+        client.try_acquire("tenant/42").await,
+        Err(Error::Backend(_))
+    ));
+
+    // lease & intents released, not left held until expiry
+    other_client
+        .try_acquire("tenant")
+        .await
+        .unwrap()
+        .expect("should acquire ancestor of released lease");
+}
+
+/// Backend implementing only the required [`LeaseBackend`] methods.
+#[derive(Debug)]
+struct CoreBackend(MemoryBackend);
+
+#[async_trait::async_trait]
+impl LeaseBackend for CoreBackend {
+    async fn put_lease(&self, lease: PutLease) -> dynamodb_lease::Result<PutOutcome> {
+        self.0.put_lease(lease).await
+    }
+
+    async fn extend_lease(
+        &self,
+        key: &str,
+        lease_v: Uuid,
+        new_lease_v: Uuid,
+        ttl: Duration,
+    ) -> dynamodb_lease::Result<bool> {
+        self.0.extend_lease(key, lease_v, new_lease_v, ttl).await
+    }
+
+    async fn release_lease(&self, key: &str, lease_v: Uuid) -> dynamodb_lease::Result<bool> {
+        self.0.release_lease(key, lease_v).await
+    }
+
+    async fn get_lease(&self, key: &str) -> dynamodb_lease::Result<Option<LeaseInfo>> {
+        self.0.get_lease(key).await
+    }
+
+    async fn validate(&self) -> dynamodb_lease::Result<()> {
+        self.0.validate().await
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn core_backend() {
+    let client = Client::builder()
+        .build_with_backend(CoreBackend(MemoryBackend::default()))
+        .await
+        .unwrap();
+
+    let lease = client.acquire("key").await.unwrap();
+    tokio::time::sleep(Duration::from_secs(120)).await;
+    assert!(!lease.is_lost());
+    assert_eq!(lease.release().await.unwrap(), ReleaseOutcome::Released);
+
+    assert!(matches!(
+        client.try_acquire_shared("key").await,
+        Err(Error::Unsupported(_))
+    ));
+    assert!(matches!(
+        client.try_acquire_many(["a", "b"]).await,
+        Err(Error::Unsupported(_))
+    ));
+}
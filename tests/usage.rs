@@ -2,9 +2,15 @@ mod util;
 
 use anyhow::Context;
 use aws_sdk_dynamodb::types::{
-    AttributeDefinition, BillingMode, KeySchemaElement, KeyType, ScalarAttributeType,
+    AttributeDefinition, AttributeValue, BillingMode, KeySchemaElement, KeyType,
+    ScalarAttributeType,
 };
-use std::time::Duration;
+use dynamodb_lease::{
+    backend::{DynamoDbBackend, LeaseBackend, SortKey, TableConfig, TableLayout},
+    ReleaseOutcome,
+};
+use std::{collections::HashMap, time::Duration};
+use tokio_stream::StreamExt;
 use util::*;
 use uuid::Uuid;
 
@@ -45,8 +51,8 @@ async fn try_acquire() {
     retry::until_ok(|| async {
         client2
             .try_acquire(&lease_key)
-            .await
-            .and_then(|maybe_lease| maybe_lease.context("did not acquire"))
+            .await?
+            .context("did not acquire")
     })
     .await;
 }
@@ -82,8 +88,8 @@ async fn local_try_acquire() {
     retry::until_ok(|| async {
         client
             .try_acquire(&lease_key)
-            .await
-            .and_then(|maybe_lease| maybe_lease.context("did not acquire"))
+            .await?
+            .context("did not acquire")
     })
     .await;
 }
@@ -227,7 +233,10 @@ async fn acquire_timeout() {
     let lease2 = client2
         .acquire_timeout(&lease_key, Duration::from_millis(100))
         .await;
-    assert!(lease2.is_err(), "should not acquire while lease1 is alive");
+    assert!(
+        matches!(lease2, Err(dynamodb_lease::Error::Timeout(_))),
+        "should not acquire while lease1 is alive"
+    );
 
     // dropping should asynchronously end the lease
     drop(lease1);
@@ -248,6 +257,10 @@ async fn init_should_check_table_exists() {
         .build_and_check_db(db_client)
         .await
         .expect_err("should check table exists");
+    assert!(
+        matches!(err, dynamodb_lease::Error::MissingTable { .. }),
+        "{err:?}"
+    );
     assert!(
         err.to_string().to_ascii_lowercase().contains("missing"),
         "{}",
@@ -284,6 +297,10 @@ async fn init_should_check_hash_key() {
         .build_and_check_db(db_client)
         .await
         .expect_err("should check hash 'key'");
+    assert!(
+        matches!(err, dynamodb_lease::Error::SchemaMismatch { .. }),
+        "{err:?}"
+    );
     assert!(
         err.to_string().to_ascii_lowercase().contains("key"),
         "{}",
@@ -320,6 +337,10 @@ async fn init_should_check_hash_key_type() {
         .build_and_check_db(db_client)
         .await
         .expect_err("should check hash key type");
+    assert!(
+        matches!(err, dynamodb_lease::Error::SchemaMismatch { .. }),
+        "{err:?}"
+    );
     assert!(
         err.to_string().to_ascii_lowercase().contains("type"),
         "{}",
@@ -356,6 +377,10 @@ async fn init_should_check_ttl() {
         .build_and_check_db(db_client)
         .await
         .expect_err("should check ttl");
+    assert!(
+        matches!(err, dynamodb_lease::Error::SchemaMismatch { .. }),
+        "{err:?}"
+    );
     assert!(
         err.to_string()
             .to_ascii_lowercase()
@@ -364,3 +389,742 @@ async fn init_should_check_ttl() {
         err
     );
 }
+
+#[tokio::test]
+async fn lease_lost_when_extend_fails() {
+    let lease_table = "test-locker-leases";
+    let db_client = localhost_dynamodb().await;
+    create_lease_table(lease_table, &db_client).await;
+
+    let client = dynamodb_lease::Client::builder()
+        .table_name(lease_table)
+        .lease_ttl_seconds(2)
+        .build_and_check_db(db_client.clone())
+        .await
+        .unwrap();
+
+    let lease_key = format!("lease_lost_when_extend_fails:{}", Uuid::new_v4());
+
+    let lease = client.acquire(&lease_key).await.unwrap();
+    assert!(!lease.is_lost());
+
+    // remove the lease item from under the holder so the next extension fails
+    db_client
+        .delete_item()
+        .table_name(lease_table)
+        .key("key", AttributeValue::S(lease_key.clone()))
+        .send()
+        .await
+        .unwrap();
+
+    tokio::time::timeout(TEST_WAIT, lease.lost())
+        .await
+        .expect("lease should be lost after failing to extend");
+    assert!(lease.is_lost());
+}
+
+#[tokio::test]
+async fn fence_token_increases() {
+    let lease_table = "test-locker-leases";
+    let db_client = localhost_dynamodb().await;
+    create_lease_table(lease_table, &db_client).await;
+
+    let client = dynamodb_lease::Client::builder()
+        .table_name(lease_table)
+        .build_and_check_db(db_client)
+        .await
+        .unwrap();
+
+    let lease_key = format!("fence_token_increases:{}", Uuid::new_v4());
+
+    let lease1 = client.acquire(&lease_key).await.unwrap();
+    let fence1 = lease1.fence_token();
+    drop(lease1);
+
+    let lease2 = tokio::time::timeout(TEST_WAIT, client.acquire(&lease_key))
+        .await
+        .expect("could not acquire after drop")
+        .expect("failed to acquire");
+    assert!(
+        lease2.fence_token() > fence1,
+        "{} should be greater than {fence1}",
+        lease2.fence_token()
+    );
+}
+
+#[tokio::test]
+async fn release() {
+    let lease_table = "test-locker-leases";
+    let db_client = localhost_dynamodb().await;
+    create_lease_table(lease_table, &db_client).await;
+
+    let client = dynamodb_lease::Client::builder()
+        .table_name(lease_table)
+        .build_and_check_db(db_client.clone())
+        .await
+        .unwrap();
+    // use 2 clients to avoid local locking / simulate distributed usage
+    let client2 = dynamodb_lease::Client::builder()
+        .table_name(lease_table)
+        .build_and_check_db(db_client)
+        .await
+        .unwrap();
+
+    let lease_key = format!("release:{}", Uuid::new_v4());
+
+    let lease1 = client.acquire(&lease_key).await.unwrap();
+    assert!(client2.try_acquire(&lease_key).await.unwrap().is_none());
+
+    // after releasing the key should be immediately acquirable
+    let outcome = lease1.release().await.unwrap();
+    assert_eq!(outcome, ReleaseOutcome::Released);
+    let lease2 = client2.try_acquire(&lease_key).await.unwrap();
+    assert!(lease2.is_some(), "should acquire after release");
+}
+
+#[tokio::test]
+async fn release_already_lost() {
+    let lease_table = "test-locker-leases";
+    let db_client = localhost_dynamodb().await;
+    create_lease_table(lease_table, &db_client).await;
+
+    let client = dynamodb_lease::Client::builder()
+        .table_name(lease_table)
+        .build_and_check_db(db_client.clone())
+        .await
+        .unwrap();
+
+    let lease_key = format!("release_already_lost:{}", Uuid::new_v4());
+
+    let lease = client.acquire(&lease_key).await.unwrap();
+
+    // remove the lease item from under the holder
+    db_client
+        .delete_item()
+        .table_name(lease_table)
+        .key("key", AttributeValue::S(lease_key.clone()))
+        .send()
+        .await
+        .unwrap();
+
+    let outcome = lease.release().await.unwrap();
+    assert_eq!(outcome, ReleaseOutcome::AlreadyLost);
+}
+
+#[tokio::test]
+async fn owner_and_metadata() {
+    let lease_table = "test-locker-leases";
+    let db_client = localhost_dynamodb().await;
+    create_lease_table(lease_table, &db_client).await;
+
+    let client = dynamodb_lease::Client::builder()
+        .table_name(lease_table)
+        .owner_id("test-owner")
+        .build_and_check_db(db_client.clone())
+        .await
+        .unwrap();
+
+    let lease_key = format!("owner_and_metadata:{}", Uuid::new_v4());
+    let metadata = HashMap::from([("job".to_owned(), "reindex".to_owned())]);
+
+    let lease = client
+        .with_metadata(metadata.clone())
+        .acquire(&lease_key)
+        .await
+        .unwrap();
+    assert_eq!(lease.owner_id(), "test-owner");
+    assert_eq!(lease.metadata(), &metadata);
+
+    let item = db_client
+        .get_item()
+        .table_name(lease_table)
+        .key("key", AttributeValue::S(lease_key.clone()))
+        .consistent_read(true)
+        .send()
+        .await
+        .unwrap()
+        .item
+        .expect("missing lease item");
+    assert_eq!(item["owner"], AttributeValue::S("test-owner".into()));
+    assert_eq!(
+        item["metadata"],
+        AttributeValue::M([("job".into(), AttributeValue::S("reindex".into()))].into())
+    );
+}
+
+#[tokio::test]
+async fn describe() {
+    let lease_table = "test-locker-leases";
+    let db_client = localhost_dynamodb().await;
+    create_lease_table(lease_table, &db_client).await;
+
+    let client = dynamodb_lease::Client::builder()
+        .table_name(lease_table)
+        .owner_id("test-owner")
+        .build_and_check_db(db_client)
+        .await
+        .unwrap();
+
+    let lease_key = format!("describe:{}", Uuid::new_v4());
+
+    assert!(client.describe(&lease_key).await.unwrap().is_none());
+
+    let lease = client.acquire(&lease_key).await.unwrap();
+    let info = client
+        .describe(&lease_key)
+        .await
+        .unwrap()
+        .expect("should describe held lease");
+    assert_eq!(info.key, lease_key);
+    assert_eq!(info.fence_token, lease.fence_token());
+    assert_eq!(info.owner_id.as_deref(), Some("test-owner"));
+    assert!(info.expiry > time::OffsetDateTime::now_utc());
+
+    lease.release().await.unwrap();
+    assert!(client.describe(&lease_key).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn list_leases_with_prefix() {
+    let lease_table = "test-locker-leases";
+    let db_client = localhost_dynamodb().await;
+    create_lease_table(lease_table, &db_client).await;
+
+    let client = dynamodb_lease::Client::builder()
+        .table_name(lease_table)
+        .build_and_check_db(db_client)
+        .await
+        .unwrap();
+
+    let prefix = format!("list_leases_with_prefix:{}:", Uuid::new_v4());
+
+    let _lease1 = client.acquire(format!("{prefix}1")).await.unwrap();
+    let _lease2 = client.acquire(format!("{prefix}2")).await.unwrap();
+    let lease3 = client.acquire(format!("{prefix}3")).await.unwrap();
+    lease3.release().await.unwrap();
+
+    let mut keys: Vec<_> = client
+        .list_leases_with_prefix(&prefix)
+        .map(|info| {
+            let info = info.unwrap();
+            assert!(!info.expired);
+            info.key
+        })
+        .collect()
+        .await;
+    keys.sort();
+    assert_eq!(keys, [format!("{prefix}1"), format!("{prefix}2")]);
+}
+
+#[tokio::test]
+async fn acquire_expired() {
+    let lease_table = "test-locker-leases";
+    let db_client = localhost_dynamodb().await;
+    create_lease_table(lease_table, &db_client).await;
+
+    let client = dynamodb_lease::Client::builder()
+        .table_name(lease_table)
+        .clock_skew_margin_seconds(1)
+        .build_and_check_db(db_client.clone())
+        .await
+        .unwrap();
+
+    let lease_key = format!("acquire_expired:{}", Uuid::new_v4());
+    let expired = time::OffsetDateTime::now_utc().unix_timestamp() - 2;
+
+    // simulate a holder, predating fencing, that crashed & never released, before ttl removes it
+    db_client
+        .put_item()
+        .table_name(lease_table)
+        .item("key", AttributeValue::S(lease_key.clone()))
+        .item("lease_expiry", AttributeValue::N(expired.to_string()))
+        .item(
+            "lease_version",
+            AttributeValue::S(Uuid::new_v4().to_string()),
+        )
+        .send()
+        .await
+        .unwrap();
+
+    let info = client.describe(&lease_key).await.unwrap().unwrap();
+    assert!(info.expired);
+    assert_eq!(info.fence_token, 0, "missing fence should default to 0");
+
+    let lease = client
+        .try_acquire(&lease_key)
+        .await
+        .unwrap()
+        .expect("should acquire expired lease");
+    assert!(lease.fence_token() > info.fence_token);
+}
+
+#[tokio::test]
+async fn shared_and_exclusive() {
+    let lease_table = "test-locker-leases";
+    let db_client = localhost_dynamodb().await;
+    create_lease_table(lease_table, &db_client).await;
+
+    let client = dynamodb_lease::Client::builder()
+        .table_name(lease_table)
+        .build_and_check_db(db_client.clone())
+        .await
+        .unwrap();
+    let other_client = dynamodb_lease::Client::builder()
+        .table_name(lease_table)
+        .build_and_check_db(db_client)
+        .await
+        .unwrap();
+
+    let lease_key = format!("shared_and_exclusive:{}", Uuid::new_v4());
+
+    // shared leases can be held concurrently, locally & by other clients
+    let shared1 = client.acquire_shared(&lease_key).await.unwrap();
+    let shared2 = client
+        .try_acquire_shared(&lease_key)
+        .await
+        .unwrap()
+        .unwrap();
+    let shared3 = other_client
+        .try_acquire_shared(&lease_key)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(shared1.is_shared());
+
+    // exclusive leases cannot be acquired while shared leases are held
+    assert!(other_client
+        .try_acquire(&lease_key)
+        .await
+        .unwrap()
+        .is_none());
+
+    shared1.release().await.unwrap();
+    shared2.release().await.unwrap();
+    assert!(other_client
+        .try_acquire(&lease_key)
+        .await
+        .unwrap()
+        .is_none());
+    assert_eq!(shared3.release().await.unwrap(), ReleaseOutcome::Released);
+
+    let exclusive = other_client.acquire_exclusive(&lease_key).await.unwrap();
+    assert!(!exclusive.is_shared());
+
+    // shared leases cannot be acquired while an exclusive lease is held
+    assert!(client
+        .try_acquire_shared(&lease_key)
+        .await
+        .unwrap()
+        .is_none());
+    drop(exclusive);
+
+    retry::until_ok(|| async {
+        client
+            .try_acquire_shared(&lease_key)
+            .await?
+            .context("did not acquire")
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn permits() {
+    let lease_table = "test-locker-leases";
+    let db_client = localhost_dynamodb().await;
+    create_lease_table(lease_table, &db_client).await;
+
+    let client = dynamodb_lease::Client::builder()
+        .table_name(lease_table)
+        .build_and_check_db(db_client)
+        .await
+        .unwrap();
+
+    let lease_key = format!("permits:{}", Uuid::new_v4());
+
+    let permit1 = client.acquire_permit(&lease_key, 2).await.unwrap();
+    let _permit2 = client
+        .try_acquire_permit(&lease_key, 2)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(client
+        .try_acquire_permit(&lease_key, 2)
+        .await
+        .unwrap()
+        .is_none());
+
+    permit1.release().await.unwrap();
+    client
+        .try_acquire_permit(&lease_key, 2)
+        .await
+        .unwrap()
+        .expect("should acquire released permit");
+}
+
+#[tokio::test]
+async fn build_with_backend() {
+    let lease_table = "test-locker-leases";
+    let db_client = localhost_dynamodb().await;
+    create_lease_table(lease_table, &db_client).await;
+
+    let backend = DynamoDbBackend::new(db_client, lease_table);
+    assert_eq!(backend.table_name(), lease_table);
+    let client = dynamodb_lease::Client::builder()
+        .build_with_backend(backend)
+        .await
+        .unwrap();
+
+    let lease_key = format!("build_with_backend:{}", Uuid::new_v4());
+    let lease = client.try_acquire(&lease_key).await.unwrap().unwrap();
+    assert!(client.try_acquire(&lease_key).await.unwrap().is_none());
+    assert_eq!(lease.release().await.unwrap(), ReleaseOutcome::Released);
+}
+
+#[tokio::test]
+async fn fair() {
+    let lease_table = "test-locker-leases";
+    let db_client = localhost_dynamodb().await;
+    create_lease_table(lease_table, &db_client).await;
+
+    let client = dynamodb_lease::Client::builder()
+        .table_name(lease_table)
+        .fair(true)
+        .build_and_check_db(db_client.clone())
+        .await
+        .unwrap();
+    let other_client = dynamodb_lease::Client::builder()
+        .table_name(lease_table)
+        .fair(true)
+        .acquire_cooldown(Duration::from_millis(50))
+        .build_and_check_db(db_client)
+        .await
+        .unwrap();
+
+    let lease_key = format!("fair:{}", Uuid::new_v4());
+
+    let lease = client.acquire(&lease_key).await.unwrap();
+    assert!(other_client
+        .try_acquire(&lease_key)
+        .await
+        .unwrap()
+        .is_none());
+
+    let waiter = tokio::spawn({
+        let lease_key = lease_key.clone();
+        async move { other_client.acquire(lease_key).await.unwrap() }
+    });
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    drop(lease);
+
+    // first waiter acquires, others queue behind it
+    let lease = waiter.await.unwrap();
+    assert!(client.try_acquire(&lease_key).await.unwrap().is_none());
+    drop(lease);
+
+    retry::until_ok(|| async {
+        client
+            .try_acquire(&lease_key)
+            .await?
+            .context("did not acquire")
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn fair_queue_expiry() {
+    let lease_table = "test-locker-leases";
+    let db_client = localhost_dynamodb().await;
+    create_lease_table(lease_table, &db_client).await;
+
+    let backend = DynamoDbBackend::new(db_client.clone(), lease_table);
+    let lease_key = format!("fair_queue_expiry:{}", Uuid::new_v4());
+    let ttl = Duration::from_secs(60);
+
+    // a new queue item is removed by ttl if abandoned
+    let ticket_expiry = time::OffsetDateTime::now_utc().unix_timestamp() + 60;
+    backend
+        .enqueue(&lease_key, Uuid::new_v4(), ttl)
+        .await
+        .unwrap();
+    assert!(lease_expiry(&db_client, lease_table, &lease_key).await >= ticket_expiry);
+
+    // simulate a released lease about to be removed by ttl
+    let expired = time::OffsetDateTime::now_utc().unix_timestamp() - 2;
+    db_client
+        .update_item()
+        .table_name(lease_table)
+        .key("key", AttributeValue::S(lease_key.clone()))
+        .update_expression("SET lease_expiry = :expired")
+        .expression_attribute_values(":expired", AttributeValue::N(expired.to_string()))
+        .send()
+        .await
+        .unwrap();
+
+    // extended to keep the queue while the ticket is alive
+    let ticket_expiry = time::OffsetDateTime::now_utc().unix_timestamp() + 60;
+    backend
+        .enqueue(&lease_key, Uuid::new_v4(), ttl)
+        .await
+        .unwrap();
+    assert!(lease_expiry(&db_client, lease_table, &lease_key).await >= ticket_expiry);
+}
+
+#[tokio::test]
+async fn hierarchical_intents_expiry() {
+    let lease_table = "test-locker-leases";
+    let db_client = localhost_dynamodb().await;
+    create_lease_table(lease_table, &db_client).await;
+
+    let client = dynamodb_lease::Client::builder()
+        .table_name(lease_table)
+        .hierarchical(true)
+        .build_and_check_db(db_client.clone())
+        .await
+        .unwrap();
+    let client2 = dynamodb_lease::Client::builder()
+        .table_name(lease_table)
+        .hierarchical(true)
+        .build_and_check_db(db_client.clone())
+        .await
+        .unwrap();
+
+    let root = format!("hierarchical_intents_expiry:{}", Uuid::new_v4());
+    let _ancestor = client2
+        .try_acquire(format!("{root}/a"))
+        .await
+        .unwrap()
+        .unwrap();
+
+    // creates an intents map on the missing "a/b" ancestor, then finds "a" held
+    let expiry = time::OffsetDateTime::now_utc().unix_timestamp() + 60;
+    assert!(client
+        .try_acquire(format!("{root}/a/b/c"))
+        .await
+        .unwrap()
+        .is_none());
+
+    // the item without intent markers is still removed by ttl
+    assert!(lease_expiry(&db_client, lease_table, &format!("{root}/a/b")).await >= expiry);
+}
+
+/// Returns the raw `lease_expiry` of the item for `key`.
+async fn lease_expiry(db_client: &aws_sdk_dynamodb::Client, table: &str, key: &str) -> i64 {
+    let item = db_client
+        .get_item()
+        .table_name(table)
+        .key("key", AttributeValue::S(key.into()))
+        .consistent_read(true)
+        .send()
+        .await
+        .unwrap()
+        .item
+        .expect("missing lease item");
+    item["lease_expiry"].as_n().unwrap().parse().unwrap()
+}
+
+#[cfg(feature = "streams")]
+#[tokio::test]
+async fn acquire_with_streams() {
+    let lease_table = "test-locker-leases-streams";
+    let db_client = localhost_dynamodb().await;
+    DynamoDbBackend::new(db_client.clone(), lease_table)
+        .create_table_if_missing(&TableConfig::default().stream(true))
+        .await
+        .unwrap();
+
+    let client = dynamodb_lease::Client::builder()
+        .table_name(lease_table)
+        .build_and_check_db(db_client.clone())
+        .await
+        .unwrap();
+    // would poll very slowly without streams
+    let other_client = dynamodb_lease::Client::builder()
+        .table_name(lease_table)
+        .acquire_cooldown(Duration::from_secs(60))
+        .streams_client(localhost_dynamodb_streams().await)
+        .build_and_check_db(db_client)
+        .await
+        .unwrap();
+
+    let lease_key = format!("acquire_with_streams:{}", Uuid::new_v4());
+
+    let lease = client.acquire(&lease_key).await.unwrap();
+    let waiter = tokio::spawn({
+        let lease_key = lease_key.clone();
+        async move { other_client.acquire(lease_key).await.unwrap() }
+    });
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    assert_eq!(lease.release().await.unwrap(), ReleaseOutcome::Released);
+
+    // woken by the stream record of the release
+    tokio::time::timeout(TEST_WAIT, waiter)
+        .await
+        .expect("should acquire promptly after release")
+        .unwrap();
+}
+
+#[tokio::test]
+async fn build_and_ensure_table() {
+    let lease_table = "test-locker-leases-ensured";
+    let db_client = localhost_dynamodb().await;
+    let table_config = TableConfig::default()
+        .provisioned_throughput(5, 5)
+        .tag("purpose", "test");
+
+    let client = dynamodb_lease::Client::builder()
+        .table_name(lease_table)
+        .build_and_ensure_table(db_client.clone(), table_config.clone())
+        .await
+        .unwrap();
+    // table already exists
+    dynamodb_lease::Client::builder()
+        .table_name(lease_table)
+        .build_and_ensure_table(db_client, table_config)
+        .await
+        .unwrap();
+
+    let lease_key = format!("build_and_ensure_table:{}", Uuid::new_v4());
+    let lease = client.try_acquire(&lease_key).await.unwrap().unwrap();
+    assert_eq!(lease.release().await.unwrap(), ReleaseOutcome::Released);
+}
+
+#[tokio::test]
+async fn table_layout() {
+    let lease_table = "test-locker-leases-layout";
+    let db_client = localhost_dynamodb().await;
+    let layout = TableLayout::default()
+        .key_attribute("pk")
+        .sort_key("sk", SortKey::Fixed("lease".into()))
+        .lease_expiry_attribute("ttl");
+
+    let client = dynamodb_lease::Client::builder()
+        .table_name(lease_table)
+        .table_layout(layout)
+        .build_and_ensure_table(db_client.clone(), TableConfig::default())
+        .await
+        .unwrap();
+
+    // the default layout does not match the table
+    let err = dynamodb_lease::Client::builder()
+        .table_name(lease_table)
+        .build_and_check_db(db_client)
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(err, dynamodb_lease::Error::SchemaMismatch { .. }),
+        "{err:?}"
+    );
+
+    let prefix = format!("table_layout:{}:", Uuid::new_v4());
+    let lease_key = format!("{prefix}1");
+    let lease = client.try_acquire(&lease_key).await.unwrap().unwrap();
+    assert!(client.try_acquire(&lease_key).await.unwrap().is_none());
+
+    let info = client.describe(&lease_key).await.unwrap().unwrap();
+    assert_eq!(info.key, lease_key);
+    assert_eq!(info.fence_token, lease.fence_token());
+
+    let keys: Vec<_> = client
+        .list_leases_with_prefix(&prefix)
+        .map(|info| info.unwrap().key)
+        .collect()
+        .await;
+    assert_eq!(keys, [lease_key.as_str()]);
+
+    assert_eq!(lease.release().await.unwrap(), ReleaseOutcome::Released);
+    assert!(client.describe(&lease_key).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn try_acquire_many() {
+    let lease_table = "test-locker-leases";
+    let db_client = localhost_dynamodb().await;
+    create_lease_table(lease_table, &db_client).await;
+
+    let client = dynamodb_lease::Client::builder()
+        .table_name(lease_table)
+        .build_and_check_db(db_client.clone())
+        .await
+        .unwrap();
+    let client2 = dynamodb_lease::Client::builder()
+        .table_name(lease_table)
+        .build_and_check_db(db_client)
+        .await
+        .unwrap();
+
+    let prefix = format!("try_acquire_many:{}:", Uuid::new_v4());
+    let (key_a, key_b, key_c) = (
+        format!("{prefix}a"),
+        format!("{prefix}b"),
+        format!("{prefix}c"),
+    );
+
+    let leases = client
+        .try_acquire_many([&key_a, &key_b])
+        .await
+        .unwrap()
+        .unwrap();
+    let info = client2.describe(&key_b).await.unwrap().unwrap();
+    assert_eq!(leases.fence_token(&key_b), Some(info.fence_token));
+
+    // all-or-nothing
+    assert!(client2
+        .try_acquire_many([&key_b, &key_c])
+        .await
+        .unwrap()
+        .is_none());
+    assert!(client2.describe(&key_c).await.unwrap().is_none());
+
+    assert_eq!(leases.release().await.unwrap(), ReleaseOutcome::Released);
+    let leases = client2
+        .try_acquire_many([&key_a, &key_b, &key_c])
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(leases.keys().count(), 3);
+}
+
+#[tokio::test]
+async fn hierarchical() {
+    let lease_table = "test-locker-leases";
+    let db_client = localhost_dynamodb().await;
+    create_lease_table(lease_table, &db_client).await;
+
+    let client = dynamodb_lease::Client::builder()
+        .table_name(lease_table)
+        .hierarchical(true)
+        .build_and_check_db(db_client.clone())
+        .await
+        .unwrap();
+    let client2 = dynamodb_lease::Client::builder()
+        .table_name(lease_table)
+        .hierarchical(true)
+        .build_and_check_db(db_client)
+        .await
+        .unwrap()
+        .namespace(format!("hierarchical:{}/", Uuid::new_v4()));
+    let client = client.namespace(client2.key_prefix());
+
+    let lease = client
+        .try_acquire("tenant/42/invoice/7")
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(client2.try_acquire("tenant/42").await.unwrap().is_none());
+    assert!(client2.try_acquire("tenant").await.unwrap().is_none());
+    let sibling = client2
+        .try_acquire("tenant/42/invoice/8")
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(lease.release().await.unwrap(), ReleaseOutcome::Released);
+    assert_eq!(sibling.release().await.unwrap(), ReleaseOutcome::Released);
+    let ancestor = client2.try_acquire("tenant/42").await.unwrap().unwrap();
+    assert!(client
+        .try_acquire("tenant/42/invoice/7")
+        .await
+        .unwrap()
+        .is_none());
+    assert_eq!(ancestor.release().await.unwrap(), ReleaseOutcome::Released);
+}